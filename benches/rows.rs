@@ -0,0 +1,88 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding wide result sets: rows copied out of the response (as `RowIterator`
+//! used to), moved out of it, or borrowed. Run with `cargo bench`.
+
+#[macro_use]
+extern crate criterion;
+extern crate cratedb;
+#[macro_use]
+extern crate serde_json;
+
+use cratedb::columns::Columns;
+use cratedb::row::Row;
+use cratedb::RowIterator;
+use criterion::{black_box, BatchSize, Criterion};
+use serde_json::Value;
+use std::sync::Arc;
+
+const ROWS: usize = 1000;
+const COLUMNS: usize = 100;
+
+fn columns() -> Columns {
+    Columns::new((0..COLUMNS).map(|i| format!("c{}", i)).collect(), None)
+}
+
+fn rows() -> Vec<Value> {
+    (0..ROWS)
+        .map(|r| {
+            Value::Array((0..COLUMNS)
+                             .map(|c| match c % 4 {
+                                      0 => json!(r * c),
+                                      1 => json!(format!("value {} of row {}", c, r)),
+                                      2 => json!([r, c, r + c]),
+                                      _ => json!({"row": r, "column": c}),
+                                  })
+                             .collect())
+        })
+        .collect()
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("wide rows");
+    group.bench_function("copied", |b| {
+        let columns = Arc::new(columns());
+        b.iter_batched(rows,
+                       |rows| {
+            let mut sum = 0;
+            for row in &rows {
+                let row = Row::with_columns(row.as_array().unwrap().to_vec(), columns.clone());
+                sum += row.get::<u64, _>(0).unwrap();
+            }
+            black_box((sum, rows))
+        },
+                       BatchSize::LargeInput)
+    });
+    group.bench_function("moved", |b| {
+        b.iter_batched(|| RowIterator::with_columns(rows(), columns()),
+                       |rows| {
+                           let sum: u64 = rows.map(|row| row.get::<u64, _>(0).unwrap()).sum();
+                           black_box(sum)
+                       },
+                       BatchSize::LargeInput)
+    });
+    group.bench_function("borrowed", |b| {
+        b.iter_batched(|| RowIterator::with_columns(rows(), columns()),
+                       |rows| {
+            let sum: u64 = rows.iter().map(|row| row.get::<u64, _>(0).unwrap()).sum();
+            black_box((sum, rows))
+        },
+                       BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
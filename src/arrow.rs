@@ -0,0 +1,357 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//!
+//! Converts query results into Arrow `RecordBatch`es (`arrow` feature).
+//!
+//! With column types (`DBCluster::set_column_types`) the schema follows them:
+//! integers and floats keep their width, `TIMESTAMP`s become millisecond
+//! timestamps (with `UTC` as time zone if they have one), `DATE`s `Date64` and
+//! arrays (and `geo_point`s) lists. Without types, each column's type is
+//! inferred from its values: booleans, `Int64`, `Float64`, strings or lists of
+//! these. `OBJECT`s, `geo_shape`s and columns of mixed values are JSON text.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! c.set_column_types(true);
+//! let (_, rows) = c.query("select * from sensors", None::<Box<NoParams>>)?;
+//! for batch in rows.record_batches(8192) {
+//!     let batch = batch?;
+//!     // ...
+//! }
+//! ```
+
+extern crate arrow_array;
+extern crate arrow_buffer;
+extern crate arrow_schema;
+extern crate serde_json;
+
+use self::arrow_array::{ArrayRef, BooleanArray, Date64Array, Float32Array, Float64Array,
+                        Int16Array, Int32Array, Int64Array, Int8Array, ListArray, NullArray,
+                        RecordBatch, RecordBatchOptions, StringArray,
+                        TimestampMillisecondArray};
+use self::arrow_buffer::{NullBuffer, OffsetBuffer};
+use self::arrow_schema::{ArrowError, DataType, Field, FieldRef, Schema, SchemaRef, TimeUnit};
+use self::serde_json::Value;
+use columns::{ColumnType, Columns};
+use convert::{mismatch, type_name, FromValue};
+use error::ConversionError;
+use row::Row;
+use std::sync::Arc;
+use std::vec;
+
+///
+/// The Arrow type a column of this type is converted to.
+///
+pub fn data_type(column_type: &ColumnType) -> DataType {
+    match *column_type {
+        ColumnType::Null => DataType::Null,
+        ColumnType::Boolean => DataType::Boolean,
+        ColumnType::Byte => DataType::Int8,
+        ColumnType::Short => DataType::Int16,
+        ColumnType::Integer => DataType::Int32,
+        ColumnType::Long => DataType::Int64,
+        ColumnType::Real => DataType::Float32,
+        ColumnType::Double => DataType::Float64,
+        ColumnType::Timestamp => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+        ColumnType::TimestampWithoutTimeZone => DataType::Timestamp(TimeUnit::Millisecond, None),
+        ColumnType::Date => DataType::Date64,
+        ColumnType::Array(ref inner) |
+        ColumnType::Set(ref inner) => list(data_type(inner)),
+        ColumnType::GeoPoint => list(DataType::Float64),
+        ColumnType::FloatVector => list(DataType::Float32),
+        _ => DataType::Utf8,
+    }
+}
+
+fn list(inner: DataType) -> DataType {
+    DataType::List(Arc::new(Field::new("item", inner, true)))
+}
+
+// The narrowest type that holds all values.
+fn infer(values: &[&Value]) -> DataType {
+    let values: Vec<&Value> = values.iter().cloned().filter(|v| !v.is_null()).collect();
+    if values.is_empty() {
+        DataType::Null
+    } else if values.iter().all(|v| v.is_boolean()) {
+        DataType::Boolean
+    } else if values.iter().all(|v| v.is_i64()) {
+        DataType::Int64
+    } else if values.iter().all(|v| v.is_number()) {
+        DataType::Float64
+    } else if values.iter().all(|v| v.is_array()) {
+        let items: Vec<&Value> = values.iter()
+            .flat_map(|v| v.as_array().unwrap().iter())
+            .collect();
+        list(infer(&items))
+    } else {
+        DataType::Utf8
+    }
+}
+
+// The values of a column, NULL where a row is too short.
+fn column(rows: &[Row], idx: usize) -> Vec<&Value> {
+    static NULL: Value = Value::Null;
+    rows.iter().map(|row| row.values().get(idx).unwrap_or(&NULL)).collect()
+}
+
+///
+/// The schema of a result: from the column types if they were requested,
+/// otherwise inferred from the rows. All fields are nullable.
+///
+pub fn schema(columns: &Columns, rows: &[Row]) -> Schema {
+    let fields: Vec<Field> = columns.names()
+        .iter()
+        .enumerate()
+        .map(|(idx, name)| {
+            let data_type = match columns.column_type(idx) {
+                Some(column_type) => data_type(column_type),
+                None => infer(&column(rows, idx)),
+            };
+            Field::new(name.as_str(), data_type, true)
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+fn primitive<T: FromValue>(values: &[&Value]) -> Result<Vec<Option<T>>, ConversionError> {
+    values.iter().map(|v| Option::<T>::from_value(v)).collect()
+}
+
+fn list_array(field: &FieldRef, values: &[&Value]) -> Result<ArrayRef, ConversionError> {
+    let mut items = vec![];
+    let mut lengths = vec![];
+    let mut valid = vec![];
+    for value in values {
+        match *value {
+            Value::Null => {
+                lengths.push(0);
+                valid.push(false);
+            }
+            Value::Array(ref values) => {
+                items.extend(values.iter());
+                lengths.push(values.len());
+                valid.push(true);
+            }
+            _ => return Err(mismatch("array", value)),
+        }
+    }
+    let nulls = if valid.iter().all(|v| *v) {
+        None
+    } else {
+        Some(NullBuffer::from(valid))
+    };
+    let child = array(field.data_type(), &items)?;
+    ListArray::try_new(field.clone(), OffsetBuffer::from_lengths(lengths), child, nulls)
+        .map(|array| Arc::new(array) as ArrayRef)
+        .map_err(|e| ConversionError::mismatch(format!("{}", DataType::List(field.clone())), e.to_string()))
+}
+
+// Converts the values of one column into an array of this type.
+fn array(data_type: &DataType, values: &[&Value]) -> Result<ArrayRef, ConversionError> {
+    let array: ArrayRef = match *data_type {
+        DataType::Null => {
+            if let Some(value) = values.iter().find(|v| !v.is_null()) {
+                return Err(ConversionError::mismatch("null", type_name(value)));
+            }
+            Arc::new(NullArray::new(values.len()))
+        }
+        DataType::Boolean => Arc::new(BooleanArray::from(primitive::<bool>(values)?)),
+        DataType::Int8 => Arc::new(Int8Array::from(primitive::<i8>(values)?)),
+        DataType::Int16 => Arc::new(Int16Array::from(primitive::<i16>(values)?)),
+        DataType::Int32 => Arc::new(Int32Array::from(primitive::<i32>(values)?)),
+        DataType::Int64 => Arc::new(Int64Array::from(primitive::<i64>(values)?)),
+        DataType::Float32 => Arc::new(Float32Array::from(primitive::<f32>(values)?)),
+        DataType::Float64 => Arc::new(Float64Array::from(primitive::<f64>(values)?)),
+        DataType::Timestamp(TimeUnit::Millisecond, ref tz) => {
+            Arc::new(TimestampMillisecondArray::from(primitive::<i64>(values)?)
+                         .with_timezone_opt(tz.clone()))
+        }
+        DataType::Date64 => Arc::new(Date64Array::from(primitive::<i64>(values)?)),
+        DataType::List(ref field) => list_array(field, values)?,
+        DataType::Utf8 => {
+            let strings: Vec<Option<String>> = values.iter()
+                .map(|v| match **v {
+                         Value::Null => None,
+                         Value::String(ref s) => Some(s.clone()),
+                         ref v => Some(v.to_string()),
+                     })
+                .collect();
+            Arc::new(StringArray::from(strings))
+        }
+        ref other => {
+            return Err(ConversionError::mismatch(other.to_string(), "an unsupported Arrow type"))
+        }
+    };
+    Ok(array)
+}
+
+///
+/// Converts rows into a record batch with this schema (see
+/// [`schema`](fn.schema.html)). Values that don't fit their field's type fail
+/// with an `ArrowError::ExternalError` wrapping the `ConversionError`.
+///
+pub fn record_batch(schema: SchemaRef, rows: &[Row]) -> Result<RecordBatch, ArrowError> {
+    let arrays = schema.fields()
+        .iter()
+        .enumerate()
+        .map(|(idx, field)| {
+            array(field.data_type(), &column(rows, idx))
+                .map_err(|e| ArrowError::ExternalError(Box::new(e.in_column(field.name()))))
+        })
+        .collect::<Result<Vec<ArrayRef>, ArrowError>>()?;
+    let options = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+    RecordBatch::try_new_with_options(schema, arrays, &options)
+}
+
+///
+/// A result converted into record batches of up to `chunk_size` rows, one at
+/// a time. All batches share a schema, created before the first batch.
+///
+pub struct RecordBatches {
+    schema: SchemaRef,
+    rows: vec::IntoIter<Row>,
+    chunk_size: usize,
+}
+
+impl RecordBatches {
+    pub fn new(columns: &Columns, rows: Vec<Row>, chunk_size: usize) -> RecordBatches {
+        RecordBatches {
+            schema: Arc::new(schema(columns, &rows)),
+            rows: rows.into_iter(),
+            chunk_size: chunk_size.max(1),
+        }
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+impl Iterator for RecordBatches {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Result<RecordBatch, ArrowError>> {
+        let chunk: Vec<Row> = self.rows.by_ref().take(self.chunk_size).collect();
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(record_batch(self.schema.clone(), &chunk))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use self::arrow_array::Array;
+    use self::arrow_array::cast::AsArray;
+    use self::arrow_array::types::{Float64Type, Int32Type, Int64Type};
+
+    fn rows(columns: Columns, rows: Vec<Value>) -> (Columns, Vec<Row>) {
+        let shared = Arc::new(columns.clone());
+        let rows = rows.into_iter()
+            .map(|row| Row::with_columns(row.as_array().unwrap().clone(), shared.clone()))
+            .collect();
+        (columns, rows)
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn typed() {
+        let columns = Columns::new(names(&["id", "ts", "tags", "payload", "pos"]),
+                                   Some(vec![ColumnType::Integer,
+                                             ColumnType::Timestamp,
+                                             ColumnType::Array(Box::new(ColumnType::String)),
+                                             ColumnType::Object,
+                                             ColumnType::GeoPoint]));
+        let (columns, rows) = rows(columns,
+                                   vec![json!([1, 1521040166535i64, ["a", "b"], {"k": 1}, [9.7, 47.4]]),
+                                        json!([null, null, null, null, null])]);
+        let schema = Arc::new(schema(&columns, &rows));
+        assert_eq!(schema.field(0).data_type(), &DataType::Int32);
+        assert_eq!(schema.field(1).data_type(),
+                   &DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())));
+        assert_eq!(schema.field(3).data_type(), &DataType::Utf8);
+
+        let batch = record_batch(schema, &rows).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let ids = batch.column(0).as_primitive::<Int32Type>();
+        assert_eq!(ids.value(0), 1);
+        assert!(ids.is_null(1));
+        let tags = batch.column(2).as_list::<i32>();
+        assert_eq!(tags.value(0).as_string::<i32>().value(1), "b");
+        assert!(tags.is_null(1));
+        assert_eq!(batch.column(3).as_string::<i32>().value(0), r#"{"k":1}"#);
+        let pos = batch.column(4).as_list::<i32>();
+        assert_eq!(pos.value(0).as_primitive::<Float64Type>().values().to_vec(),
+                   vec![9.7, 47.4]);
+    }
+
+    #[test]
+    fn inferred() {
+        let columns = Columns::new(names(&["n", "x", "s", "mixed", "nested", "nothing"]), None);
+        let (columns, rows) = rows(columns,
+                                   vec![json!([1, 1, "a", 1, [[1], []], null]),
+                                        json!([2, 1.5, null, "b", null, null])]);
+        let schema = schema(&columns, &rows);
+        let types: Vec<&DataType> = schema.fields().iter().map(|f| f.data_type()).collect();
+        assert_eq!(types,
+                   vec![&DataType::Int64,
+                        &DataType::Float64,
+                        &DataType::Utf8,
+                        &DataType::Utf8,
+                        &list(list(DataType::Int64)),
+                        &DataType::Null]);
+
+        let batch = record_batch(Arc::new(schema), &rows).unwrap();
+        assert_eq!(batch.column(1).as_primitive::<Float64Type>().value(0), 1.0);
+        assert_eq!(batch.column(3).as_string::<i32>().value(0), "1");
+        let nested = batch.column(4).as_list::<i32>().value(0);
+        assert_eq!(nested.as_list::<i32>().value(0).as_primitive::<Int64Type>().value(0), 1);
+        assert_eq!(batch.column(5).logical_null_count(), 2);
+    }
+
+    #[test]
+    fn errors() {
+        let columns = Columns::new(names(&["id"]), Some(vec![ColumnType::Short]));
+        let (columns, rows) = rows(columns, vec![json!([1]), json!(["x"])]);
+        let schema = Arc::new(schema(&columns, &rows));
+        match record_batch(schema, &rows) {
+            Err(ArrowError::ExternalError(e)) => {
+                assert_eq!(e.to_string(), "Column 'id': expected i16, got string")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn chunks() {
+        let columns = Columns::new(names(&["id"]), None);
+        let (columns, rows) = rows(columns, (0..5).map(|i| json!([i])).collect());
+        let batches = RecordBatches::new(&columns, rows, 2);
+        assert_eq!(batches.schema().field(0).data_type(), &DataType::Int64);
+        let sizes: Vec<usize> = batches.map(|b| b.unwrap().num_rows()).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+
+        let (columns, rows) = self::rows(Columns::new(vec![], None), vec![json!([])]);
+        let batch = record_batch(Arc::new(schema(&columns, &rows)), &rows).unwrap();
+        assert_eq!((batch.num_rows(), batch.num_columns()), (1, 0));
+    }
+}
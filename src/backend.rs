@@ -14,42 +14,75 @@
 
 extern crate hyper;
 extern crate hyper_rustls;
+extern crate rustls;
+extern crate flate2;
 
 use self::hyper::{Client, Url};
+use self::hyper::client::ProxyConfig as HyperProxyConfig;
 use self::hyper::net::{HttpConnector, HttpsConnector};
-use self::hyper::header::{Headers, ContentType};
+use self::hyper::header::{Headers, ContentType, Authorization, Basic, ContentEncoding,
+                          AcceptEncoding, Encoding, UserAgent, qitem};
 use self::hyper_rustls::TlsClient;
-use self::hyper::client::Body;
+use self::rustls::ClientConfig;
+use self::hyper::client::{Body, Response};
+use self::flate2::Compression;
+use self::flate2::read::GzDecoder;
+use self::flate2::write::GzEncoder;
 use self::hyper::status::StatusCode;
 
-use std::io::Read;
-use error::BackendError;
-use std::borrow::Cow;
+use std::io::{self, Read, Write};
+use error::{BackendError, CrateDBConfigurationError};
+use config::{TlsConfig, ProxyConfig, CompressionConfig};
+use proxy::{self, TunnelConnector};
+use tls;
+use std::sync::Arc;
 use std::convert::Into;
 use std::clone::Clone;
 use common::to_hex_string;
 use std::path::PathBuf;
 
+///
+/// The transport a node is reached with, derived from its URL's scheme.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum UrlType {
-    Encryped,
+    /// `https`: TLS on top of TCP
+    Encrypted,
+    /// `http`: plain TCP
     Plaintext,
 }
 
-impl Into<UrlType> for String {
-    fn into(self) -> UrlType {
-        match self.as_ref() {
-            "https" => UrlType::Encryped,
-            _ => UrlType::Plaintext,
+impl UrlType {
+    fn of(url: &Url) -> Result<UrlType, BackendError> {
+        match url.scheme() {
+            "http" => Ok(UrlType::Plaintext),
+            "https" => Ok(UrlType::Encrypted),
+            other => Err(BackendError::new(format!("Unknown URL scheme '{}'", other))),
         }
     }
 }
 
-pub type DefaultHTTPBackend = HTTPBackend<&'static str>;
+pub type DefaultHTTPBackend = HTTPBackend;
+
+/// The `User-Agent` sent unless another one is configured.
+pub const DEFAULT_USER_AGENT: &str = concat!("rust-cratedb/", env!("CARGO_PKG_VERSION"));
+
+///
+/// Computes additional headers for each request, e.g. to propagate a trace id.
+/// Receives the URL the request is sent to.
+///
+pub type HeaderProvider = dyn Fn(&Url) -> Vec<(String, String)> + Send + Sync;
 
-#[derive(Clone)]
+///
+/// The outcome of a request, by HTTP status. New outcomes may be added, so
+/// matches need a wildcard arm.
+///
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum BackendResult {
     NotFound = 404,
     NotAuthorized = 403,
+    Conflict = 409,
     Timeout = 504,
     Error = 500,
     Ok = 200,
@@ -79,7 +112,7 @@ pub trait Backend {
                    to: Option<String>,
                    bucket: &str,
                    sha1: &[u8],
-                   f: &mut Read)
+                   f: &mut dyn Read)
                    -> Result<BackendResult, BackendError>;
 
     ///
@@ -115,45 +148,210 @@ pub trait Backend {
                   to: Option<String>,
                   bucket: &str,
                   sha1: &[u8])
-                  -> Result<(BackendResult, Box<Read>), BackendError>;
+                  -> Result<(BackendResult, Box<dyn Read + Send>), BackendError>;
 }
 
-pub struct HTTPBackend<H: Into<Cow<'static, str>> + Clone> {
-    client_factory: HTTPClientFactory<H>,
+pub struct HTTPBackend {
+    client_factory: HTTPClientFactory,
+    credentials: Option<Basic>,
+    default_schema: Option<String>,
+    compression: CompressionConfig,
+    user_agent: String,
+    custom_headers: Vec<(String, String)>,
+    header_provider: Option<Arc<HeaderProvider>>,
 }
 
 
-impl<H: Into<Cow<'static, str>> + Clone> HTTPBackend<H> {
-    pub fn new() -> DefaultHTTPBackend {
-        HTTPBackend { client_factory: HTTPClientFactory::<H>::new() }
+impl HTTPBackend {
+    pub fn new() -> HTTPBackend {
+        HTTPBackend {
+            client_factory: HTTPClientFactory::new(),
+            credentials: None,
+            default_schema: None,
+            compression: CompressionConfig::default(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            custom_headers: vec![],
+            header_provider: None,
+        }
+    }
+
+    pub fn with_proxy(proxy: ProxyConfig) -> HTTPBackend {
+        HTTPBackend {
+            client_factory: HTTPClientFactory::with_proxy(proxy),
+            credentials: None,
+            default_schema: None,
+            compression: CompressionConfig::default(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            custom_headers: vec![],
+            header_provider: None,
+        }
+    }
+
+    ///
+    /// Authenticates every request (SQL and BLOB) with HTTP basic auth.
+    ///
+    pub fn set_credentials<S: Into<String>>(&mut self, user: S, password: Option<String>) {
+        self.credentials = Some(Basic {
+                                    username: user.into(),
+                                    password,
+                                });
+    }
+
+    ///
+    /// Sets the schema unqualified table names in SQL statements resolve to.
+    ///
+    pub fn set_default_schema<S: Into<String>>(&mut self, schema: S) {
+        self.default_schema = Some(schema.into());
+    }
+
+    ///
+    /// Applies custom TLS settings (root certificates, client certificates, ...)
+    /// to all `https` connections.
+    ///
+    /// # Errors
+    /// If a certificate or key file can't be read or parsed.
+    ///
+    pub fn set_tls(&mut self, tls: &TlsConfig) -> Result<(), CrateDBConfigurationError> {
+        self.client_factory.tls = Arc::new(tls::client_config(tls)?);
+        Ok(())
+    }
+
+    ///
+    /// Sends all requests through an HTTP proxy, except for hosts the proxy
+    /// configuration excludes.
+    ///
+    pub fn set_proxy(&mut self, proxy: ProxyConfig) {
+        self.client_factory.proxy = Some(proxy);
+    }
+
+    ///
+    /// Compresses large SQL request bodies and/or accepts compressed responses.
+    ///
+    pub fn set_compression(&mut self, compression: CompressionConfig) {
+        self.compression = compression;
+    }
+
+    ///
+    /// Identifies the application with this `User-Agent` instead of
+    /// [`DEFAULT_USER_AGENT`](constant.DEFAULT_USER_AGENT.html).
+    ///
+    /// # Errors
+    /// If the value contains line breaks or other control characters.
+    ///
+    pub fn set_user_agent<S: Into<String>>(&mut self,
+                                           user_agent: S)
+                                           -> Result<(), CrateDBConfigurationError> {
+        let user_agent = user_agent.into();
+        validate_header("User-Agent", &user_agent)
+            .map_err(|description| CrateDBConfigurationError { description })?;
+        self.user_agent = user_agent;
+        Ok(())
+    }
+
+    ///
+    /// Adds a header to every request (SQL and BLOB). Setting the same header
+    /// again replaces its value.
+    ///
+    /// # Errors
+    /// If the name isn't a valid header name or is one the driver sets itself
+    /// (`Authorization`, `Proxy-Authorization`, `Content-Type`,
+    /// `Content-Encoding`), or the value contains line breaks or other control
+    /// characters.
+    ///
+    pub fn set_header<N, V>(&mut self, name: N, value: V) -> Result<(), CrateDBConfigurationError>
+        where N: Into<String>,
+              V: Into<String>
+    {
+        let (name, value) = (name.into(), value.into());
+        validate_header(&name, &value)
+            .map_err(|description| CrateDBConfigurationError { description })?;
+        self.custom_headers
+            .retain(|(n, _)| !n.eq_ignore_ascii_case(&name));
+        self.custom_headers.push((name, value));
+        Ok(())
+    }
+
+    ///
+    /// Calls the provider before every request (SQL and BLOB) and adds the
+    /// headers it returns, after (and possibly replacing) the static ones.
+    /// Requests with invalid headers, or headers the driver sets itself, fail
+    /// with a `BackendError`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cratedb::Cluster;
+    /// let mut c: Cluster = Cluster::from_string("localhost").unwrap();
+    /// c.backend.set_header_provider(|_url| vec![("X-Request-Id".to_string(), "42".to_string())]);
+    /// ```
+    ///
+    pub fn set_header_provider<F>(&mut self, provider: F)
+        where F: Fn(&Url) -> Vec<(String, String)> + Send + Sync + 'static
+    {
+        self.header_provider = Some(Arc::new(provider));
     }
 
-    pub fn with_proxy(host: H, port: u16) -> HTTPBackend<H> {
-        HTTPBackend { client_factory: HTTPClientFactory::with_proxy(host, port) }
+    // Headers that are sent along with every request to the URL.
+    fn headers(&self, to: &Url) -> Result<Headers, BackendError> {
+        let mut headers = self.client_factory.proxy_headers(to);
+        headers.set(UserAgent(self.user_agent.clone()));
+        if let Some(ref credentials) = self.credentials {
+            headers.set(Authorization(credentials.clone()));
+        }
+        if self.compression.accept_compressed {
+            headers.set(AcceptEncoding(vec![qitem(Encoding::Gzip)]));
+        }
+        for (name, value) in &self.custom_headers {
+            headers.set_raw(name.clone(), vec![value.as_bytes().to_vec()]);
+        }
+        if let Some(ref provider) = self.header_provider {
+            for (name, value) in provider(to) {
+                validate_header(&name, &value).map_err(BackendError::new)?;
+                headers.set_raw(name, vec![value.into_bytes()]);
+            }
+        }
+        Ok(headers)
+    }
+
+    // The payload, gzipped if it reaches the configured threshold.
+    fn encode(&self, payload: String, headers: &mut Headers) -> Result<Vec<u8>, BackendError> {
+        match self.compression.request_threshold {
+            Some(threshold) if payload.len() >= threshold => {
+                headers.set(ContentEncoding(vec![Encoding::Gzip]));
+                gzip(payload.as_bytes()).map_err(BackendError::from_io)
+            }
+            _ => Ok(payload.into_bytes()),
+        }
+    }
+}
+
+
+impl Default for HTTPBackend {
+    fn default() -> HTTPBackend {
+        HTTPBackend::new()
     }
 }
 
 
-impl<H: Into<Cow<'static, str>> + Clone> Backend for HTTPBackend<H> {
+impl Backend for HTTPBackend {
     fn execute(&self,
                to: Option<String>,
                payload: String)
                -> Result<(BackendResult, String), BackendError> {
 
         let to_raw = to.ok_or_else(|| BackendError::new("No URL specified".to_owned()))?;
-        let to = Url::parse(&to_raw).unwrap();
-        let client = self.client_factory
-            .client(match to.scheme() {
-                        "http" => UrlType::Plaintext,
-                        "https" => UrlType::Encryped,
-                        _ => return Err(BackendError::new("Unknown URL scheme".to_string())),
-                    });
+        let to = Url::parse(&to_raw).map_err(BackendError::from_parser)?;
+        let client = self.client_factory.client(&to)?;
 
-        let mut headers = Headers::new();
+        let mut headers = self.headers(&to)?;
         headers.set(ContentType::json());
-        let mut response = client
+        if let Some(ref schema) = self.default_schema {
+            headers.set_raw("Default-Schema", vec![schema.as_bytes().to_vec()]);
+        }
+        let body = self.encode(payload, &mut headers)?;
+        let response = client
             .post(to)
-            .body(&payload)
+            .body(&body[..])
             .headers(headers)
             .send()
             .map_err(BackendError::from_transport)?;
@@ -161,7 +359,7 @@ impl<H: Into<Cow<'static, str>> + Clone> Backend for HTTPBackend<H> {
         let mut buf = String::new();
 
         let status = parse_status(&response.status);
-        response
+        decoded(response)
             .read_to_string(&mut buf)
             .map_err(BackendError::from_io)?;
         Ok((status, buf))
@@ -171,12 +369,14 @@ impl<H: Into<Cow<'static, str>> + Clone> Backend for HTTPBackend<H> {
                    to: Option<String>,
                    bucket: &str,
                    sha1: &[u8],
-                   mut f: &mut Read)
+                   mut f: &mut dyn Read)
                    -> Result<BackendResult, BackendError> {
         if let Ok(to) = make_blob_url(to, bucket, sha1) {
-            let client = self.client_factory.client(to.scheme().to_string());
+            let client = self.client_factory.client(&to)?;
+            let headers = self.headers(&to)?;
             client
                 .put(to)
+                .headers(headers)
                 .body(Body::ChunkedBody(&mut f))
                 .send()
                 .map(|r| parse_status(&r.status))
@@ -193,9 +393,11 @@ impl<H: Into<Cow<'static, str>> + Clone> Backend for HTTPBackend<H> {
                    sha1: &[u8])
                    -> Result<BackendResult, BackendError> {
         if let Ok(to) = make_blob_url(to, bucket, sha1) {
-            let client = self.client_factory.client(to.scheme().to_string());
+            let client = self.client_factory.client(&to)?;
+            let headers = self.headers(&to)?;
             client
                 .delete(to)
+                .headers(headers)
                 .send()
                 .map(|r| parse_status(&r.status))
                 .map_err(BackendError::from_transport)
@@ -208,13 +410,18 @@ impl<H: Into<Cow<'static, str>> + Clone> Backend for HTTPBackend<H> {
                   to: Option<String>,
                   bucket: &str,
                   sha1: &[u8])
-                  -> Result<(BackendResult, Box<Read>), BackendError> {
+                  -> Result<(BackendResult, Box<dyn Read + Send>), BackendError> {
 
         if let Ok(to) = make_blob_url(to, bucket, sha1) {
-            let client = self.client_factory.client(to.scheme().to_string());
+            let client = self.client_factory.client(&to)?;
+            let headers = self.headers(&to)?;
 
-            let response = client.get(to).send().map_err(BackendError::from_transport)?;
-            Ok((parse_status(&response.status), Box::new(response)))
+            let response = client
+                .get(to)
+                .headers(headers)
+                .send()
+                .map_err(BackendError::from_transport)?;
+            Ok((parse_status(&response.status), decoded(response)))
         } else {
             Err(BackendError::new("Invalid blob url".to_string()))
         }
@@ -224,17 +431,58 @@ impl<H: Into<Cow<'static, str>> + Clone> Backend for HTTPBackend<H> {
 
 fn parse_status(code: &StatusCode) -> BackendResult {
     match *code {
-        StatusCode::Ok | StatusCode::Created | StatusCode::Accepted => BackendResult::Ok, 
-        StatusCode::BadRequest |
-        //StatusCode::InternalServerError => BackendResult::Error,
+        StatusCode::Ok | StatusCode::Created | StatusCode::Accepted | StatusCode::NoContent => {
+            BackendResult::Ok
+        }
+        StatusCode::NotFound => BackendResult::NotFound,
+        StatusCode::Conflict => BackendResult::Conflict,
         StatusCode::Unauthorized |
         StatusCode::Forbidden |
         StatusCode::MethodNotAllowed => BackendResult::NotAuthorized,
-        StatusCode::RequestTimeout => BackendResult::Timeout,
+        StatusCode::RequestTimeout | StatusCode::GatewayTimeout => BackendResult::Timeout,
         _ => BackendResult::Error,
     }
 }
 
+// Header names are RFC 7230 tokens, values must not break out of their line.
+// Headers the backend sets itself, which custom headers must not replace.
+const RESERVED_HEADERS: &[&str] = &["Authorization",
+                                    "Proxy-Authorization",
+                                    "Content-Type",
+                                    "Content-Encoding"];
+
+fn validate_header(name: &str, value: &str) -> Result<(), String> {
+    let is_token = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    if name.is_empty() || !name.chars().all(is_token) {
+        Err(format!("Invalid header name '{}'", name))
+    } else if RESERVED_HEADERS.iter().any(|reserved| reserved.eq_ignore_ascii_case(name)) {
+        Err(format!("Header '{}' is set by the driver", name))
+    } else if value.chars().any(|c| c.is_control() && c != '\t') {
+        Err(format!("Invalid value for header '{}'", name))
+    } else {
+        Ok(())
+    }
+}
+
+fn gzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 4), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+// The response body, decompressed if the server gzipped it.
+fn decoded(response: Response) -> Box<dyn Read + Send> {
+    let gzipped = response
+        .headers
+        .get::<ContentEncoding>()
+        .is_some_and(|encoding| encoding.contains(&Encoding::Gzip));
+    if gzipped {
+        Box::new(GzDecoder::new(response))
+    } else {
+        Box::new(response)
+    }
+}
+
 fn make_blob_url(to: Option<String>, bucket: &str, sha1: &[u8]) -> Result<Url, BackendError> {
     let to_raw = to.ok_or_else(|| BackendError::new("No URL specified".to_owned()))?;
     if let Ok(to) = Url::parse(&to_raw) {
@@ -257,45 +505,72 @@ fn make_blob_url(to: Option<String>, bucket: &str, sha1: &[u8]) -> Result<Url, B
 /// Client factory for loosely coupling the backend's clients. Mainly for testability.
 ///
 trait ClientFactory {
-    fn client<T>(&self, u: T) -> Client where T: Into<UrlType>;
+    fn client(&self, to: &Url) -> Result<Client, BackendError>;
 }
 
-struct HTTPClientFactory<H: Into<Cow<'static, str>> + Clone> {
-    use_proxy: bool,
-    proxy_host: H,
-    proxy_port: u16,
+struct HTTPClientFactory {
+    proxy: Option<ProxyConfig>,
+    tls: Arc<ClientConfig>,
 }
 
-impl<H: Into<Cow<'static, str>> + Clone> HTTPClientFactory<H> {
-    pub fn new() -> HTTPClientFactory<&'static str> {
+impl HTTPClientFactory {
+    pub fn new() -> HTTPClientFactory {
         HTTPClientFactory {
-            proxy_host: "",
-            proxy_port: 0,
-            use_proxy: false,
+            proxy: None,
+            tls: Arc::new(tls::default_client_config()),
         }
     }
 
-    pub fn with_proxy(host: H, port: u16) -> HTTPClientFactory<H> {
+    pub fn with_proxy(proxy: ProxyConfig) -> HTTPClientFactory {
         HTTPClientFactory {
-            proxy_host: host,
-            proxy_port: port,
-            use_proxy: true,
+            proxy: Some(proxy),
+            tls: Arc::new(tls::default_client_config()),
         }
     }
-}
 
-impl<H: Into<Cow<'static, str>> + Clone> ClientFactory for HTTPClientFactory<H> {
-    fn client<T>(&self, u: T) -> Client
-        where T: Into<UrlType>
-    {
-        if self.use_proxy {
-            Client::with_http_proxy(self.proxy_host.clone(), self.proxy_port)
-        } else {
-            match u.into() {
-                UrlType::Encryped => Client::with_connector(HttpConnector {}),
-                UrlType::Plaintext => Client::with_connector(HttpsConnector::new(TlsClient::new())),
+    fn tls_client(&self) -> TlsClient {
+        TlsClient { cfg: self.tls.clone() }
+    }
+
+    // The proxy to use for the URL, if any
+    fn proxy_for(&self, to: &Url) -> Option<&ProxyConfig> {
+        self.proxy
+            .as_ref()
+            .filter(|proxy| !to.host_str().is_some_and(|host| proxy.is_excluded(host)))
+    }
+
+    // Plain HTTP requests go to the proxy itself, so they have to carry its
+    // credentials. Tunnelled requests authenticate with the CONNECT request.
+    fn proxy_headers(&self, to: &Url) -> Headers {
+        let mut headers = Headers::new();
+        if let (Some(proxy), Ok(UrlType::Plaintext)) = (self.proxy_for(to), UrlType::of(to)) {
+            if let Some(credentials) = proxy::authorization(proxy) {
+                headers.set_raw("Proxy-Authorization", vec![credentials.into_bytes()]);
             }
         }
+        headers
+    }
+}
+
+impl ClientFactory for HTTPClientFactory {
+    fn client(&self, to: &Url) -> Result<Client, BackendError> {
+        let url_type = UrlType::of(to)?;
+        Ok(match (self.proxy_for(to), url_type) {
+               (Some(proxy), UrlType::Plaintext) => {
+                   Client::with_proxy_config(HyperProxyConfig::new("http",
+                                                                   proxy.host.clone(),
+                                                                   proxy.port,
+                                                                   HttpConnector,
+                                                                   self.tls_client()))
+               }
+               (Some(proxy), UrlType::Encrypted) => {
+                   Client::with_connector(TunnelConnector::new(proxy.clone(), self.tls_client()))
+               }
+               (None, UrlType::Plaintext) => Client::with_connector(HttpConnector),
+               (None, UrlType::Encrypted) => {
+                   Client::with_connector(HttpsConnector::new(self.tls_client()))
+               }
+           })
     }
 }
 
@@ -320,6 +595,157 @@ mod tests {
 
     }
 
+    #[test]
+    fn url_types() {
+        let url_type = |u: &str| UrlType::of(&Url::parse(u).unwrap());
+        assert_eq!(url_type("http://localhost:4200/_sql"), Ok(UrlType::Plaintext));
+        assert_eq!(url_type("https://localhost:4200/_sql"), Ok(UrlType::Encrypted));
+        assert_eq!(url_type("ftp://localhost:4200/_sql"),
+                   Err(BackendError::new("Unknown URL scheme 'ftp'".to_string())));
+    }
+
+    #[test]
+    fn unknown_scheme() {
+        let backend = DefaultHTTPBackend::new();
+        assert_eq!(backend
+                       .execute(Some("ftp://localhost/_sql".to_string()), "{}".to_string())
+                       .err(),
+                   Some(BackendError::new("Unknown URL scheme 'ftp'".to_string())));
+        assert_eq!(backend
+                       .delete_blob(Some("ftp://localhost/_blobs".to_string()), "b", b"12")
+                       .err(),
+                   Some(BackendError::new("Unknown URL scheme 'ftp'".to_string())));
+        assert!(backend
+                    .execute(Some("not a url".to_string()), "{}".to_string())
+                    .is_err());
+    }
+
+    #[test]
+    fn credential_headers() {
+        let url = Url::parse("http://localhost:4200/_sql").unwrap();
+        let mut backend = DefaultHTTPBackend::new();
+        assert!(backend.headers(&url).unwrap().get::<Authorization<Basic>>().is_none());
+
+        backend.set_credentials("crate", Some("secret".to_string()));
+        let headers = backend.headers(&url).unwrap();
+        let auth = headers.get::<Authorization<Basic>>().unwrap();
+        assert_eq!(auth.0.username, "crate");
+        assert_eq!(auth.0.password, Some("secret".to_string()));
+    }
+
+    #[test]
+    fn proxy_headers() {
+        let mut proxy = ProxyConfig::new("proxy", 3128);
+        proxy.user = Some("user".to_string());
+        proxy.password = Some("secret".to_string());
+        proxy.no_proxy = vec!["localhost".to_string()];
+        let backend = DefaultHTTPBackend::with_proxy(proxy);
+
+        let proxy_auth = |u: &str| {
+            backend
+                .headers(&Url::parse(u).unwrap())
+                .unwrap()
+                .get_raw("Proxy-Authorization")
+                .map(|v| v[0].clone())
+        };
+        assert_eq!(proxy_auth("http://db:4200/_sql"),
+                   Some(b"Basic dXNlcjpzZWNyZXQ=".to_vec()));
+        assert_eq!(proxy_auth("https://db:4200/_sql"), None);
+        assert_eq!(proxy_auth("http://localhost:4200/_sql"), None);
+    }
+
+    #[test]
+    fn compression() {
+        let url = Url::parse("http://localhost:4200/_sql").unwrap();
+        let mut backend = DefaultHTTPBackend::new();
+        let mut headers = Headers::new();
+        assert_eq!(backend.encode("select 1".to_string(), &mut headers),
+                   Ok(b"select 1".to_vec()));
+        assert!(headers.get::<ContentEncoding>().is_none());
+        assert!(backend.headers(&url).unwrap().get::<AcceptEncoding>().is_none());
+
+        backend.set_compression(CompressionConfig {
+                                    request_threshold: Some(9),
+                                    accept_compressed: true,
+                                });
+        assert_eq!(backend.encode("select 1".to_string(), &mut headers),
+                   Ok(b"select 1".to_vec()));
+        assert!(headers.get::<ContentEncoding>().is_none());
+
+        let payload = "select 10".repeat(100);
+        let body = backend.encode(payload.clone(), &mut headers).unwrap();
+        assert!(body.len() < payload.len());
+        assert_eq!(headers.get::<ContentEncoding>(),
+                   Some(&ContentEncoding(vec![Encoding::Gzip])));
+        let mut decompressed = String::new();
+        GzDecoder::new(&body[..])
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, payload);
+
+        assert_eq!(backend.headers(&url).unwrap().get::<AcceptEncoding>(),
+                   Some(&AcceptEncoding(vec![qitem(Encoding::Gzip)])));
+    }
+
+    #[test]
+    fn custom_headers() {
+        let url = Url::parse("http://localhost:4200/_sql").unwrap();
+        let raw = |backend: &HTTPBackend, name: &str| {
+            backend
+                .headers(&url)
+                .map(|h| h.get_raw(name).map(|v| String::from_utf8_lossy(&v[0]).into_owned()))
+        };
+        let mut backend = DefaultHTTPBackend::new();
+        assert_eq!(raw(&backend, "User-Agent"), Ok(Some(DEFAULT_USER_AGENT.to_string())));
+
+        backend.set_user_agent("metrics-service/2.1").unwrap();
+        backend.set_header("X-Team", "ops").unwrap();
+        backend.set_header("x-team", "sre").unwrap();
+        assert_eq!(raw(&backend, "User-Agent"), Ok(Some("metrics-service/2.1".to_string())));
+        assert_eq!(raw(&backend, "X-Team"), Ok(Some("sre".to_string())));
+
+        backend.set_header_provider(|to| vec![("X-Path".to_string(), to.path().to_string())]);
+        assert_eq!(raw(&backend, "X-Path"), Ok(Some("/_sql".to_string())));
+        assert_eq!(raw(&backend, "X-Team"), Ok(Some("sre".to_string())));
+
+        backend.set_header_provider(|_| vec![("X-Bad".to_string(), "a\r\nb".to_string())]);
+        assert_eq!(raw(&backend, "X-Bad"),
+                   Err(BackendError::new("Invalid value for header 'X-Bad'".to_string())));
+        backend.set_header_provider(|_| vec![("Authorization".to_string(), "Basic".to_string())]);
+        let error = "Header 'Authorization' is set by the driver".to_string();
+        assert_eq!(raw(&backend, "Authorization"), Err(BackendError::new(error)));
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let mut backend = DefaultHTTPBackend::new();
+        assert!(backend.set_header("", "a").is_err());
+        assert!(backend.set_header("X Team", "a").is_err());
+        assert!(backend.set_header("X-Team:", "a").is_err());
+        assert!(backend.set_header("X-Team", "a\nInjected: yes").is_err());
+        assert!(backend.set_user_agent("agent\r\n").is_err());
+        assert!(backend.set_header("X-Team", "a\tb").is_ok());
+        for name in &["Authorization", "proxy-authorization", "Content-Type", "CONTENT-ENCODING"] {
+            assert_eq!(backend.set_header(*name, "a").unwrap_err().description,
+                       format!("Header '{}' is set by the driver", name));
+        }
+    }
+
+    #[test]
+    fn status_codes() {
+        assert_eq!(parse_status(&StatusCode::Ok), BackendResult::Ok);
+        assert_eq!(parse_status(&StatusCode::Created), BackendResult::Ok);
+        assert_eq!(parse_status(&StatusCode::NoContent), BackendResult::Ok);
+        assert_eq!(parse_status(&StatusCode::NotFound), BackendResult::NotFound);
+        assert_eq!(parse_status(&StatusCode::Conflict), BackendResult::Conflict);
+        assert_eq!(parse_status(&StatusCode::Unauthorized),
+                   BackendResult::NotAuthorized);
+        assert_eq!(parse_status(&StatusCode::GatewayTimeout), BackendResult::Timeout);
+        assert_eq!(parse_status(&StatusCode::BadRequest), BackendResult::Error);
+        assert_eq!(parse_status(&StatusCode::InternalServerError),
+                   BackendResult::Error);
+    }
+
     #[test]
     fn invalid_make_blob_url() {
         assert_eq!(make_blob_url(None, "a", b"1234"),
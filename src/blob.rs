@@ -19,7 +19,9 @@ use error::{BlobError, BackendError, CrateDBError};
 use dbcluster::DBCluster;
 use backend::{Backend, BackendResult};
 use dbcluster::{Loadbalancing, EndpointType};
-use common::sha1_digest;
+use common::{sha1_digest, stream_len};
+use instrument;
+use metrics::{CountingReader, Operation};
 use sql::{QueryRunner, Nothing as NoParams};
 use row::ByIndex;
 use self::hex::FromHex;
@@ -108,7 +110,7 @@ pub trait BlobContainer {
     /// let _ = c.get(&my_blob_ref);
     /// ```
     ///
-    fn get(&self, blob: &BlobRef) -> Result<Box<Read>, BlobError>;
+    fn get(&self, blob: &BlobRef) -> Result<Box<dyn Read + Send>, BlobError>;
 }
 
 
@@ -118,19 +120,25 @@ impl<T: Backend + Sized> BlobContainer for DBCluster<T> {
                                               table: TBL,
                                               blob: &mut B)
                                               -> Result<BlobRef, BlobError> {
-        match sha1_digest(blob) {
-            Ok(sha1) => {
+        match sha1_digest(blob).and_then(|sha1| stream_len(blob).map(|size| (sha1, size))) {
+            Ok((sha1, size)) => {
                 let url = self.get_endpoint(EndpointType::Blob);
+                let node = url.as_deref().map(instrument::node);
                 let table = table.into();
-                match self.backend
+                let operation = instrument::blob(Operation::Upload,
+                                                 url.as_deref(),
+                                                 &table,
+                                                 &sha1);
+                let result = match self.backend
                           .upload_blob(url, &table, &sha1, blob)
                           .map_err(BlobError::Transport) {
                     Ok(status) => {
                         match status {
                             BackendResult::Ok => {
+                                self.metrics().bytes_uploaded(node.as_deref(), size);
                                 Ok(BlobRef {
-                                       table: table,
-                                       sha1: sha1,
+                                       table,
+                                       sha1,
                                    })
                             }
                             BackendResult::NotFound => {
@@ -141,6 +149,10 @@ impl<T: Backend + Sized> BlobContainer for DBCluster<T> {
                                 Err(BlobError::Action(CrateDBError::new("Could not upload BLOB: Not authorized.",
                                                                         "403")))
                             }
+                            BackendResult::Conflict => {
+                                Err(BlobError::Action(CrateDBError::new("Could not upload BLOB. Already exists.",
+                                                                        "409")))
+                            }
                             BackendResult::Timeout => {
                                 Err(BlobError::Action(CrateDBError::new("Could not upload BLOB. Timed out.",
                                                                         "408")))
@@ -152,8 +164,10 @@ impl<T: Backend + Sized> BlobContainer for DBCluster<T> {
                         }
                     }
                     Err(e) => Err(e),
-                }
-            }   
+                };
+                operation.finish(&result, None, &**self.metrics());
+                result
+            }
             Err(io) => Err(BlobError::Transport(BackendError::from_io(io))),
         }
     }
@@ -162,7 +176,11 @@ impl<T: Backend + Sized> BlobContainer for DBCluster<T> {
 
     fn delete(&self, blob: BlobRef) -> Result<(), BlobError> {
         let url = self.get_endpoint(EndpointType::Blob);
-        match self.backend
+        let operation = instrument::blob(Operation::Delete,
+                                         url.as_deref(),
+                                         &blob.table,
+                                         &blob.sha1);
+        let result = match self.backend
                   .delete_blob(url, &blob.table, &blob.sha1)
                   .map_err(BlobError::Transport) {
             Ok(status) => {
@@ -176,6 +194,10 @@ impl<T: Backend + Sized> BlobContainer for DBCluster<T> {
                         Err(BlobError::Action(CrateDBError::new("Could not delete BLOB: Not authorized.",
                                                                 "403")))
                     }
+                    BackendResult::Conflict => {
+                        Err(BlobError::Action(CrateDBError::new("Could not delete BLOB. Conflict.",
+                                                                "409")))
+                    }
                     BackendResult::Timeout => {
                         Err(BlobError::Action(CrateDBError::new("Could not delete BLOB. Timed out.",
                                                                 "408")))
@@ -187,18 +209,28 @@ impl<T: Backend + Sized> BlobContainer for DBCluster<T> {
                 }
             }
             Err(e) => Err(e),
-        }
+        };
+        operation.finish(&result, None, &**self.metrics());
+        result
     }
 
 
-    fn get(&self, blob: &BlobRef) -> Result<Box<Read>, BlobError> {
+    fn get(&self, blob: &BlobRef) -> Result<Box<dyn Read + Send>, BlobError> {
         let url = self.get_endpoint(EndpointType::Blob);
-        match self.backend
+        let node = url.as_deref().map(instrument::node);
+        let operation = instrument::blob(Operation::Fetch,
+                                         url.as_deref(),
+                                         &blob.table,
+                                         &blob.sha1);
+        let result = match self.backend
                   .fetch_blob(url, &blob.table, &blob.sha1)
                   .map_err(BlobError::Transport) {
             Ok((status, content)) => {
                 match status {
-                    BackendResult::Ok => Ok(content),
+                    BackendResult::Ok => {
+                        Ok(Box::new(CountingReader::new(content, node, self.metrics().clone())) as
+                           Box<dyn Read + Send>)
+                    }
                     BackendResult::NotFound => {
                         Err(BlobError::Action(CrateDBError::new("Could not fetch BLOB. Not found.",
                                                                 "404")))
@@ -207,6 +239,10 @@ impl<T: Backend + Sized> BlobContainer for DBCluster<T> {
                         Err(BlobError::Action(CrateDBError::new("Could not fetch BLOB: Not authorized.",
                                                                 "403")))
                     }
+                    BackendResult::Conflict => {
+                        Err(BlobError::Action(CrateDBError::new("Could not fetch BLOB. Conflict.",
+                                                                "409")))
+                    }
                     BackendResult::Timeout => {
                         Err(BlobError::Action(CrateDBError::new("Could not fetch BLOB. Timed out.",
                                                                 "408")))
@@ -218,7 +254,9 @@ impl<T: Backend + Sized> BlobContainer for DBCluster<T> {
                 }
             }
             Err(e) => Err(e),
-        }
+        };
+        operation.finish(&result, None, &**self.metrics());
+        result
     }
 
     fn list<TBL: Into<String>>(&self, table: TBL) -> Result<Vec<BlobRef>, BlobError> {
@@ -0,0 +1,277 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate serde_json;
+
+use self::serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+///
+/// The type of a result column, as reported by CrateDB when column types are
+/// requested (see `DBCluster::set_column_types`).
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnType {
+    Null,
+    NotSupported,
+    Byte,
+    Boolean,
+    String,
+    Ip,
+    Double,
+    Real,
+    Short,
+    Integer,
+    Long,
+    Timestamp,
+    TimestampWithoutTimeZone,
+    Object,
+    GeoPoint,
+    GeoShape,
+    UncheckedObject,
+    Time,
+    Numeric,
+    Date,
+    Bit,
+    Json,
+    Character,
+    FloatVector,
+    Array(Box<ColumnType>),
+    Set(Box<ColumnType>),
+    /// A type this driver doesn't know (yet), by its id
+    Other(i64),
+}
+
+impl ColumnType {
+    ///
+    /// Reads a type from CrateDB's `col_types`: an id, or `[id, inner]` for
+    /// arrays and sets.
+    ///
+    pub fn from_value(value: &Value) -> ColumnType {
+        match *value {
+            Value::Array(ref parts) if parts.len() == 2 => {
+                let inner = Box::new(ColumnType::from_value(&parts[1]));
+                match parts[0].as_i64() {
+                    Some(100) => ColumnType::Array(inner),
+                    Some(101) => ColumnType::Set(inner),
+                    Some(id) => ColumnType::Other(id),
+                    None => ColumnType::NotSupported,
+                }
+            }
+            _ => value.as_i64().map(ColumnType::from_id).unwrap_or(ColumnType::NotSupported),
+        }
+    }
+
+    fn from_id(id: i64) -> ColumnType {
+        match id {
+            0 => ColumnType::Null,
+            1 => ColumnType::NotSupported,
+            2 => ColumnType::Byte,
+            3 => ColumnType::Boolean,
+            4 => ColumnType::String,
+            5 => ColumnType::Ip,
+            6 => ColumnType::Double,
+            7 => ColumnType::Real,
+            8 => ColumnType::Short,
+            9 => ColumnType::Integer,
+            10 => ColumnType::Long,
+            11 => ColumnType::Timestamp,
+            12 => ColumnType::Object,
+            13 => ColumnType::GeoPoint,
+            14 => ColumnType::GeoShape,
+            15 => ColumnType::TimestampWithoutTimeZone,
+            16 => ColumnType::UncheckedObject,
+            20 => ColumnType::Time,
+            22 => ColumnType::Numeric,
+            24 => ColumnType::Date,
+            25 => ColumnType::Bit,
+            26 => ColumnType::Json,
+            27 => ColumnType::Character,
+            28 => ColumnType::FloatVector,
+            id => ColumnType::Other(id),
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ColumnType::Null => write!(f, "null"),
+            ColumnType::NotSupported => write!(f, "not supported"),
+            ColumnType::Byte => write!(f, "byte"),
+            ColumnType::Boolean => write!(f, "boolean"),
+            ColumnType::String => write!(f, "text"),
+            ColumnType::Ip => write!(f, "ip"),
+            ColumnType::Double => write!(f, "double precision"),
+            ColumnType::Real => write!(f, "real"),
+            ColumnType::Short => write!(f, "smallint"),
+            ColumnType::Integer => write!(f, "integer"),
+            ColumnType::Long => write!(f, "bigint"),
+            ColumnType::Timestamp => write!(f, "timestamp with time zone"),
+            ColumnType::TimestampWithoutTimeZone => write!(f, "timestamp without time zone"),
+            ColumnType::Object => write!(f, "object"),
+            ColumnType::GeoPoint => write!(f, "geo_point"),
+            ColumnType::GeoShape => write!(f, "geo_shape"),
+            ColumnType::UncheckedObject => write!(f, "unchecked object"),
+            ColumnType::Time => write!(f, "time with time zone"),
+            ColumnType::Numeric => write!(f, "numeric"),
+            ColumnType::Date => write!(f, "date"),
+            ColumnType::Bit => write!(f, "bit"),
+            ColumnType::Json => write!(f, "json"),
+            ColumnType::Character => write!(f, "character"),
+            ColumnType::FloatVector => write!(f, "float_vector"),
+            ColumnType::Array(ref inner) => write!(f, "array({})", inner),
+            ColumnType::Set(ref inner) => write!(f, "set({})", inner),
+            ColumnType::Other(id) => write!(f, "type {}", id),
+        }
+    }
+}
+
+///
+/// The columns of a result set: their names in order and, if they were
+/// requested, their types. Shared by the result's rows.
+///
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Columns {
+    names: Vec<String>,
+    positions: HashMap<String, usize>,
+    types: Option<Vec<ColumnType>>,
+}
+
+impl Columns {
+    pub fn new(names: Vec<String>, types: Option<Vec<ColumnType>>) -> Columns {
+        let positions = names.iter().enumerate().map(|(i, name)| (name.clone(), i)).collect();
+        Columns {
+            names,
+            positions,
+            types,
+        }
+    }
+
+    ///
+    /// Columns from a map of names to positions (as `Row::new` takes them).
+    /// Positions missing from the map are named by their index.
+    ///
+    pub fn from_positions(positions: HashMap<String, usize>) -> Columns {
+        let len = positions.values().max().map_or(0, |&max| max + 1);
+        let mut names: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+        for (name, &i) in &positions {
+            names[i] = name.clone();
+        }
+        Columns {
+            names,
+            positions,
+            types: None,
+        }
+    }
+
+    ///
+    /// The column names, in order.
+    ///
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    ///
+    /// The name of the column at this index.
+    ///
+    pub fn name(&self, idx: usize) -> Option<&str> {
+        self.names.get(idx).map(String::as_str)
+    }
+
+    ///
+    /// The index of the column with this name.
+    ///
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.positions.get(name).cloned()
+    }
+
+    ///
+    /// The column types, if they were requested.
+    ///
+    pub fn types(&self) -> Option<&[ColumnType]> {
+        self.types.as_deref()
+    }
+
+    ///
+    /// The type of the column at this index, if types were requested.
+    ///
+    pub fn column_type(&self, idx: usize) -> Option<&ColumnType> {
+        self.types.as_ref().and_then(|types| types.get(idx))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn types() {
+        assert_eq!(ColumnType::from_value(&json!(10)), ColumnType::Long);
+        assert_eq!(ColumnType::from_value(&json!(15)), ColumnType::TimestampWithoutTimeZone);
+        assert_eq!(ColumnType::from_value(&json!([100, 4])),
+                   ColumnType::Array(Box::new(ColumnType::String)));
+        assert_eq!(ColumnType::from_value(&json!([100, [100, 9]])),
+                   ColumnType::Array(Box::new(ColumnType::Array(Box::new(ColumnType::Integer)))));
+        assert_eq!(ColumnType::from_value(&json!(99)), ColumnType::Other(99));
+        assert_eq!(ColumnType::from_value(&json!("text")), ColumnType::NotSupported);
+        assert_eq!(ColumnType::from_value(&json!([100, 13])).to_string(),
+                   "array(geo_point)");
+    }
+
+    #[test]
+    fn columns() {
+        let columns = Columns::new(vec!["id".to_string(), "name".to_string()],
+                                   Some(vec![ColumnType::Long, ColumnType::String]));
+        assert_eq!(columns.len(), 2);
+        assert_eq!(columns.names(), &["id".to_string(), "name".to_string()][..]);
+        assert_eq!(columns.name(1), Some("name"));
+        assert_eq!(columns.name(2), None);
+        assert_eq!(columns.position("name"), Some(1));
+        assert_eq!(columns.column_type(0), Some(&ColumnType::Long));
+
+        let mut positions = HashMap::new();
+        positions.insert("b".to_string(), 1);
+        positions.insert("a".to_string(), 0);
+        let columns = Columns::from_positions(positions);
+        assert_eq!(columns.names(), &["a".to_string(), "b".to_string()][..]);
+        assert_eq!(columns.types(), None);
+        assert_eq!(columns.column_type(0), None);
+    }
+
+    #[test]
+    fn sparse_positions() {
+        let mut positions = HashMap::new();
+        positions.insert("id".to_string(), 0);
+        positions.insert("temp".to_string(), 2);
+        let columns = Columns::from_positions(positions);
+        assert_eq!(columns.names(), &["id".to_string(), "1".to_string(), "temp".to_string()][..]);
+        assert_eq!(columns.len(), 3);
+        for name in &["id", "temp"] {
+            assert_eq!(columns.name(columns.position(name).unwrap()), Some(*name));
+        }
+        assert_eq!(columns.position("1"), None);
+        assert_eq!(Columns::from_positions(HashMap::new()).len(), 0);
+    }
+}
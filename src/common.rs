@@ -34,6 +34,13 @@ pub fn sha1_digest<B: Read + Seek>(input: &mut B) -> Result<Vec<u8>, Error> {
     Ok((*sha1.as_ref()).to_vec())
 }
 
+// The size of the stream, which is rewound to the start afterwards.
+pub fn stream_len<B: Seek>(input: &mut B) -> Result<u64, Error> {
+    let len = input.seek(SeekFrom::End(0))?;
+    input.seek(SeekFrom::Start(0))?;
+    Ok(len)
+}
+
 ///
 /// Creates a string object from an array of bytes
 ///
@@ -66,6 +73,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(unused_variables)]
     fn string_from_hex() {
         let sha_1234 = [113, 16, 237, 164, 208, 158, 6, 42, 165, 228, 163, 144, 176, 165, 114,
                         172, 13, 44, 2, 32];
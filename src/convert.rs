@@ -0,0 +1,314 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate serde;
+extern crate serde_json;
+
+use self::serde::de::DeserializeOwned;
+use self::serde_json::Value;
+use columns::ColumnType;
+use error::ConversionError;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+///
+/// Converts a value of a result row into a Rust type. Implement it for your
+/// own types to read them with [`Row::get`](../row/struct.Row.html#method.get),
+/// or wrap types that implement `Deserialize` in [`Json`](struct.Json.html).
+///
+/// # Example
+///
+/// ```rust
+/// use cratedb::convert::FromValue;
+/// use cratedb::error::ConversionError;
+/// # extern crate serde_json;
+/// # extern crate cratedb;
+/// # fn main() {
+/// struct Celsius(f64);
+///
+/// impl FromValue for Celsius {
+///     fn from_value(value: &serde_json::Value) -> Result<Celsius, ConversionError> {
+///         f64::from_value(value).map(Celsius)
+///     }
+/// }
+/// # }
+/// ```
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, ConversionError>;
+
+    ///
+    /// Checks the column's type, if CrateDB reported it (see
+    /// `DBCluster::set_column_types`), before its values are converted. Accepts
+    /// any type by default.
+    ///
+    fn check_type(_column_type: &ColumnType) -> Result<(), ConversionError> {
+        Ok(())
+    }
+}
+
+///
+/// Converts a value of a column, checking the column's type first if it is
+/// known.
+///
+pub fn from_column<T: FromValue>(value: &Value,
+                                 column_type: Option<&ColumnType>)
+                                 -> Result<T, ConversionError> {
+    if let Some(column_type) = column_type {
+        T::check_type(column_type)?;
+    }
+    T::from_value(value)
+}
+
+///
+/// The JSON type of a value, for error messages.
+///
+pub fn type_name(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(ref n) if n.is_f64() => "double",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+///
+/// The error for a value that isn't of the `expected` type.
+///
+pub fn mismatch(expected: &str, value: &Value) -> ConversionError {
+    match *value {
+        Value::Null => ConversionError::Null { column: None },
+        _ => ConversionError::mismatch(expected, type_name(value)),
+    }
+}
+
+// Integers that don't fit into the requested type.
+fn out_of_range(expected: &str, value: &Value) -> ConversionError {
+    ConversionError::mismatch(expected, format!("{} {} (out of range)", type_name(value), value))
+}
+
+macro_rules! from_integer {
+    ($($t:ty),*) => {
+        $(
+        impl FromValue for $t {
+            fn from_value(value: &Value) -> Result<$t, ConversionError> {
+                let expected = stringify!($t);
+                if let Some(i) = value.as_i64() {
+                    <$t>::try_from(i).map_err(|_| out_of_range(expected, value))
+                } else if let Some(u) = value.as_u64() {
+                    <$t>::try_from(u).map_err(|_| out_of_range(expected, value))
+                } else {
+                    Err(mismatch(expected, value))
+                }
+            }
+        }
+        )*
+    }
+}
+
+from_integer!(i8, i16, i32, i64, u8, u16, u32, u64, isize, usize);
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<f64, ConversionError> {
+        value.as_f64().ok_or_else(|| mismatch("f64", value))
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: &Value) -> Result<f32, ConversionError> {
+        value.as_f64().map(|f| f as f32).ok_or_else(|| mismatch("f32", value))
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<bool, ConversionError> {
+        value.as_bool().ok_or_else(|| mismatch("bool", value))
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<String, ConversionError> {
+        value.as_str().map(String::from).ok_or_else(|| mismatch("String", value))
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Value, ConversionError> {
+        Ok(value.clone())
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Option<T>, ConversionError> {
+        match *value {
+            Value::Null => Ok(None),
+            _ => T::from_value(value).map(Some),
+        }
+    }
+
+    fn check_type(column_type: &ColumnType) -> Result<(), ConversionError> {
+        T::check_type(column_type)
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Vec<T>, ConversionError> {
+        match *value {
+            Value::Array(ref values) => values.iter().map(T::from_value).collect(),
+            _ => Err(mismatch("array", value)),
+        }
+    }
+
+    fn check_type(column_type: &ColumnType) -> Result<(), ConversionError> {
+        match *column_type {
+            ColumnType::Array(ref inner) |
+            ColumnType::Set(ref inner) => T::check_type(inner),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<K, T> FromValue for HashMap<K, T>
+    where K: From<String> + Eq + Hash,
+          T: FromValue
+{
+    fn from_value(value: &Value) -> Result<HashMap<K, T>, ConversionError> {
+        match *value {
+            Value::Object(ref map) => {
+                map.iter()
+                    .map(|(k, v)| T::from_value(v).map(|v| (K::from(k.clone()), v)))
+                    .collect()
+            }
+            _ => Err(mismatch("object", value)),
+        }
+    }
+}
+
+impl<K, T> FromValue for BTreeMap<K, T>
+    where K: From<String> + Ord,
+          T: FromValue
+{
+    fn from_value(value: &Value) -> Result<BTreeMap<K, T>, ConversionError> {
+        match *value {
+            Value::Object(ref map) => {
+                map.iter()
+                    .map(|(k, v)| T::from_value(v).map(|v| (K::from(k.clone()), v)))
+                    .collect()
+            }
+            _ => Err(mismatch("object", value)),
+        }
+    }
+}
+
+///
+/// Reads any type that implements `Deserialize`, e.g. a struct for an
+/// `OBJECT` column.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[derive(Deserialize)]
+/// struct Address { street: String, city: String }
+///
+/// let Json(address) = row.get::<Json<Address>, _>("address")?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Json<T>(pub T);
+
+impl<T: DeserializeOwned> FromValue for Json<T> {
+    fn from_value(value: &Value) -> Result<Json<T>, ConversionError> {
+        T::deserialize(value)
+            .map(Json)
+            .map_err(|e| match *value {
+                         Value::Null => ConversionError::Null { column: None },
+                         _ => ConversionError::mismatch(std::any::type_name::<T>(), e.to_string()),
+                     })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[test]
+    fn primitives() {
+        assert_eq!(i64::from_value(&json!(-3)), Ok(-3));
+        assert_eq!(u8::from_value(&json!(255)), Ok(255));
+        assert_eq!(u64::from_value(&json!(u64::MAX)), Ok(u64::MAX));
+        assert_eq!(f64::from_value(&json!(1)), Ok(1.0));
+        assert_eq!(f32::from_value(&json!(0.5)), Ok(0.5));
+        assert_eq!(bool::from_value(&json!(true)), Ok(true));
+        assert_eq!(String::from_value(&json!("a")), Ok("a".to_string()));
+        assert_eq!(Value::from_value(&json!({"a": 1})), Ok(json!({"a": 1})));
+    }
+
+    #[test]
+    fn mismatches() {
+        assert_eq!(u8::from_value(&json!(256)),
+                   Err(ConversionError::mismatch("u8", "integer 256 (out of range)")));
+        assert_eq!(u32::from_value(&json!(-1)).unwrap_err().to_string(),
+                   "Expected u32, got integer -1 (out of range)");
+        assert_eq!(i64::from_value(&json!(1.5)),
+                   Err(ConversionError::mismatch("i64", "double")));
+        assert_eq!(String::from_value(&json!(1)),
+                   Err(ConversionError::mismatch("String", "integer")));
+        assert_eq!(bool::from_value(&Value::Null),
+                   Err(ConversionError::Null { column: None }));
+        assert_eq!(Vec::<i64>::from_value(&json!([1, null])),
+                   Err(ConversionError::Null { column: None }));
+    }
+
+    #[test]
+    fn containers() {
+        assert_eq!(Option::<i64>::from_value(&Value::Null), Ok(None));
+        assert_eq!(Option::<i64>::from_value(&json!(1)), Ok(Some(1)));
+        assert_eq!(Vec::<i64>::from_value(&json!([1, 2])), Ok(vec![1, 2]));
+        assert_eq!(Vec::<Option<i64>>::from_value(&json!([1, null])),
+                   Ok(vec![Some(1), None]));
+        assert!(Vec::<i64>::from_value(&json!([1, "a"])).is_err());
+        assert_eq!(Vec::<Vec<i64>>::from_value(&json!([[1], []])),
+                   Ok(vec![vec![1], vec![]]));
+
+        let map = HashMap::<String, i64>::from_value(&json!({"a": 1, "b": 2})).unwrap();
+        assert_eq!(map["b"], 2);
+        let map = BTreeMap::<String, String>::from_value(&json!({"a": "x"})).unwrap();
+        assert_eq!(map["a"], "x");
+        assert!(HashMap::<String, i64>::from_value(&json!([1])).is_err());
+    }
+
+    #[test]
+    fn user_types() {
+        assert_eq!(Json::<Point>::from_value(&json!({"x": 1, "y": 2})),
+                   Ok(Json(Point { x: 1, y: 2 })));
+        match Json::<Point>::from_value(&json!({"x": 1})) {
+            Err(ConversionError::TypeMismatch { expected, actual, .. }) => {
+                assert!(expected.ends_with("Point"));
+                assert_eq!(actual, "missing field `y`");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(Json::<Point>::from_value(&Value::Null),
+                   Err(ConversionError::Null { column: None }));
+    }
+}
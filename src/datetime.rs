@@ -0,0 +1,314 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//!
+//! `chrono` support for `TIMESTAMP` and `DATE` columns (`chrono` feature).
+//!
+//! CrateDB returns both as milliseconds since the epoch. A `TIMESTAMP WITH TIME
+//! ZONE` is a point in time and reads as `DateTime<Utc>` (or
+//! `DateTime<FixedOffset>`); a `TIMESTAMP WITHOUT TIME ZONE` stores the wall
+//! clock time as if it were UTC and reads as `NaiveDateTime`, so no time zone is
+//! made up for it. ISO 8601 strings are accepted as well.
+//!
+//! Parameters are sent as epoch milliseconds, either wrapped in a `Timestamp`
+//! or, in structs, with `#[serde(with = "cratedb::datetime::epoch_millis")]`.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use cratedb::datetime::Timestamp;
+//! let since = Utc::now() - Duration::days(1);
+//! let (_, rows) = c.query("select ts from logs where ts > ?", Some(Box::new((Timestamp(since),))))?;
+//! for row in rows {
+//!     let ts: DateTime<Utc> = row.get("ts")?;
+//! }
+//! ```
+
+extern crate chrono;
+extern crate serde;
+extern crate serde_json;
+
+use self::chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use self::serde::{Serialize, Serializer};
+use self::serde_json::Value;
+use columns::ColumnType;
+use convert::{mismatch, FromValue};
+use error::ConversionError;
+use std::str::FromStr;
+
+///
+/// A date/time type that CrateDB represents as milliseconds since the epoch.
+///
+pub trait EpochMillis: Sized {
+    fn to_millis(&self) -> i64;
+
+    ///
+    /// `None` if the value is out of the type's range.
+    ///
+    fn from_millis(millis: i64) -> Option<Self>;
+}
+
+impl EpochMillis for DateTime<Utc> {
+    fn to_millis(&self) -> i64 {
+        self.timestamp_millis()
+    }
+
+    fn from_millis(millis: i64) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp_millis(millis)
+    }
+}
+
+impl EpochMillis for DateTime<FixedOffset> {
+    fn to_millis(&self) -> i64 {
+        self.timestamp_millis()
+    }
+
+    fn from_millis(millis: i64) -> Option<DateTime<FixedOffset>> {
+        DateTime::from_timestamp_millis(millis).map(|dt| dt.fixed_offset())
+    }
+}
+
+impl EpochMillis for NaiveDateTime {
+    fn to_millis(&self) -> i64 {
+        self.and_utc().timestamp_millis()
+    }
+
+    fn from_millis(millis: i64) -> Option<NaiveDateTime> {
+        DateTime::from_timestamp_millis(millis).map(|dt| dt.naive_utc())
+    }
+}
+
+impl EpochMillis for NaiveDate {
+    fn to_millis(&self) -> i64 {
+        self.and_hms_opt(0, 0, 0).unwrap().to_millis()
+    }
+
+    fn from_millis(millis: i64) -> Option<NaiveDate> {
+        DateTime::from_timestamp_millis(millis).map(|dt| dt.date_naive())
+    }
+}
+
+// Epoch milliseconds or an ISO 8601 string.
+fn from_value<T: EpochMillis + FromStr>(value: &Value, expected: &str) -> Result<T, ConversionError> {
+    match *value {
+        Value::Number(ref n) => {
+            n.as_i64()
+                .and_then(T::from_millis)
+                .ok_or_else(|| ConversionError::mismatch(expected, format!("{} (out of range)", n)))
+        }
+        Value::String(ref s) => {
+            s.parse()
+                .map_err(|_| ConversionError::mismatch(expected, format!("string \"{}\"", s)))
+        }
+        _ => Err(mismatch(expected, value)),
+    }
+}
+
+// When the column's type is known, the other kind of timestamp is refused
+// instead of making up or dropping a time zone.
+fn check_type(column_type: &ColumnType,
+              expected: &str,
+              refused: Option<ColumnType>)
+              -> Result<(), ConversionError> {
+    if Some(column_type) == refused.as_ref() {
+        Err(ConversionError::mismatch(expected, column_type.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+macro_rules! from_timestamp {
+    ($($t:ty => $name:expr, $refused:expr);*) => {
+        $(
+        impl FromValue for $t {
+            fn from_value(value: &Value) -> Result<$t, ConversionError> {
+                from_value(value, $name)
+            }
+
+            fn check_type(column_type: &ColumnType) -> Result<(), ConversionError> {
+                check_type(column_type, $name, $refused)
+            }
+        }
+        )*
+    }
+}
+
+from_timestamp!(DateTime<Utc> => "timestamp with time zone",
+                Some(ColumnType::TimestampWithoutTimeZone);
+                DateTime<FixedOffset> => "timestamp with time zone",
+                Some(ColumnType::TimestampWithoutTimeZone);
+                NaiveDateTime => "timestamp without time zone", Some(ColumnType::Timestamp);
+                NaiveDate => "date", None);
+
+///
+/// Sends a date/time parameter as milliseconds since the epoch.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timestamp<T>(pub T);
+
+impl<T: EpochMillis> Serialize for Timestamp<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(self.0.to_millis())
+    }
+}
+
+///
+/// (De)serializes date/time fields as epoch milliseconds, for use with
+/// `#[serde(with = "cratedb::datetime::epoch_millis")]`.
+///
+pub mod epoch_millis {
+    use super::serde::de::Error;
+    use super::serde::{Deserialize, Deserializer, Serializer};
+    use super::EpochMillis;
+
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+        where T: EpochMillis,
+              S: Serializer
+    {
+        serializer.serialize_i64(value.to_millis())
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+        where T: EpochMillis,
+              D: Deserializer<'de>
+    {
+        let millis = i64::deserialize(deserializer)?;
+        T::from_millis(millis)
+            .ok_or_else(|| D::Error::custom(format!("timestamp {} out of range", millis)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::chrono::{NaiveTime, TimeZone};
+    use columns::Columns;
+    use row::{Row, RowRef};
+    use std::sync::Arc;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Event {
+        name: String,
+        #[serde(with = "epoch_millis")]
+        at: DateTime<Utc>,
+    }
+
+    fn utc() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2018, 3, 14, 15, 9, 26).unwrap() + chrono::Duration::milliseconds(535)
+    }
+
+    #[test]
+    fn from_millis() {
+        assert_eq!(DateTime::<Utc>::from_value(&json!(1521040166535i64)), Ok(utc()));
+        assert_eq!(DateTime::<FixedOffset>::from_value(&json!(1521040166535i64)),
+                   Ok(utc().fixed_offset()));
+        assert_eq!(NaiveDateTime::from_value(&json!(1521040166535i64)),
+                   Ok(utc().naive_utc()));
+        assert_eq!(NaiveDate::from_value(&json!(1520985600000i64)),
+                   Ok(NaiveDate::from_ymd_opt(2018, 3, 14).unwrap()));
+        assert_eq!(Option::<DateTime<Utc>>::from_value(&Value::Null), Ok(None));
+    }
+
+    #[test]
+    fn from_strings() {
+        assert_eq!(DateTime::<Utc>::from_value(&json!("2018-03-14T15:09:26.535Z")),
+                   Ok(utc()));
+        let offset = DateTime::<FixedOffset>::from_value(&json!("2018-03-14T16:09:26.535+01:00"))
+            .unwrap();
+        assert_eq!(offset.offset().local_minus_utc(), 3600);
+        assert_eq!(offset, utc());
+        assert_eq!(NaiveDateTime::from_value(&json!("2018-03-14T15:09:26.535")),
+                   Ok(NaiveDate::from_ymd_opt(2018, 3, 14)
+                          .unwrap()
+                          .and_time(NaiveTime::from_hms_milli_opt(15, 9, 26, 535).unwrap())));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(DateTime::<Utc>::from_value(&json!("yesterday")),
+                   Err(ConversionError::mismatch("timestamp with time zone",
+                                                 "string \"yesterday\"")));
+        assert_eq!(NaiveDate::from_value(&json!(true)),
+                   Err(ConversionError::mismatch("date", "boolean")));
+        assert_eq!(NaiveDateTime::from_value(&Value::Null),
+                   Err(ConversionError::Null { column: None }));
+        assert!(DateTime::<Utc>::from_value(&json!(i64::MAX)).is_err());
+    }
+
+    #[test]
+    fn parameters() {
+        assert_eq!(serde_json::to_value((Timestamp(utc()), Timestamp(utc().naive_utc())))
+                       .unwrap(),
+                   json!([1521040166535i64, 1521040166535i64]));
+        assert_eq!(serde_json::to_value(Timestamp(NaiveDate::from_ymd_opt(2018, 3, 14).unwrap()))
+                       .unwrap(),
+                   json!(1520985600000i64));
+
+        let event = Event {
+            name: "release".to_string(),
+            at: utc(),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json, json!({"name": "release", "at": 1521040166535i64}));
+        assert_eq!(serde_json::from_value::<Event>(json).unwrap(), event);
+    }
+
+    #[test]
+    fn column_types() {
+        let columns = Columns::new(vec!["with_tz".to_string(),
+                                        "without_tz".to_string(),
+                                        "millis".to_string(),
+                                        "history".to_string()],
+                                   Some(vec![ColumnType::Timestamp,
+                                             ColumnType::TimestampWithoutTimeZone,
+                                             ColumnType::Long,
+                                             ColumnType::Array(Box::new(ColumnType::TimestampWithoutTimeZone))]));
+        let values = vec![json!(1521040166535i64),
+                          json!(1521040166535i64),
+                          json!(1521040166535i64),
+                          json!([1521040166535i64])];
+        let row = Row::with_columns(values.clone(), Arc::new(columns.clone()));
+
+        assert_eq!(row.get::<DateTime<Utc>, _>("with_tz"), Ok(utc()));
+        assert_eq!(row.get::<NaiveDateTime, _>("without_tz"), Ok(utc().naive_utc()));
+        assert_eq!(row.get::<DateTime<Utc>, _>("millis"), Ok(utc()));
+        assert_eq!(row.get::<NaiveDateTime, _>("millis"), Ok(utc().naive_utc()));
+        assert_eq!(row.get::<Vec<NaiveDateTime>, _>("history"), Ok(vec![utc().naive_utc()]));
+
+        assert_eq!(row.get::<DateTime<Utc>, _>("without_tz"),
+                   Err(ConversionError::TypeMismatch {
+                           column: Some("without_tz".to_string()),
+                           expected: "timestamp with time zone".to_string(),
+                           actual: "timestamp without time zone".to_string(),
+                       }));
+        assert_eq!(row.get::<NaiveDateTime, _>("with_tz"),
+                   Err(ConversionError::TypeMismatch {
+                           column: Some("with_tz".to_string()),
+                           expected: "timestamp without time zone".to_string(),
+                           actual: "timestamp with time zone".to_string(),
+                       }));
+        assert!(row.get::<Option<DateTime<FixedOffset>>, _>("without_tz").is_err());
+        assert!(row.get::<Vec<DateTime<Utc>>, _>("history").is_err());
+
+        let row = RowRef::new(&values, &columns);
+        assert!(row.get::<DateTime<Utc>, _>("without_tz").is_err());
+        assert!(row.get::<NaiveDateTime, _>("with_tz").is_err());
+        assert_eq!(row.get::<NaiveDateTime, _>("without_tz"), Ok(utc().naive_utc()));
+
+        let untyped = Row::with_columns(values,
+                                        Arc::new(Columns::new(columns.names().to_vec(), None)));
+        assert_eq!(untyped.get::<DateTime<Utc>, _>("without_tz"), Ok(utc()));
+    }
+}
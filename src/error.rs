@@ -78,6 +78,104 @@ impl fmt::Display for CrateDBConfigurationError {
 }
 
 
+///
+/// A value of a row that could not be converted into the requested type.
+/// `column` is the name of the column, if known.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConversionError {
+    /// The value is NULL, but the type isn't an `Option`
+    Null { column: Option<String> },
+
+    /// The value has a different type, e.g. `expected: "i64", actual: "string"`
+    TypeMismatch {
+        column: Option<String>,
+        expected: String,
+        actual: String,
+    },
+
+    /// The row has no column of that name
+    NoSuchColumn { column: String },
+
+    /// The row has fewer columns than that
+    IndexOutOfRange { index: usize, len: usize },
+}
+
+impl ConversionError {
+    pub fn mismatch<S1, S2>(expected: S1, actual: S2) -> ConversionError
+        where S1: Into<String>,
+              S2: Into<String>
+    {
+        ConversionError::TypeMismatch {
+            column: None,
+            expected: expected.into(),
+            actual: actual.into(),
+        }
+    }
+
+    ///
+    /// Names the column the value came from, unless it is named already.
+    ///
+    pub fn in_column<S: Into<String>>(self, name: S) -> ConversionError {
+        match self {
+            ConversionError::Null { column: None } => {
+                ConversionError::Null { column: Some(name.into()) }
+            }
+            ConversionError::TypeMismatch {
+                column: None,
+                expected,
+                actual,
+            } => {
+                ConversionError::TypeMismatch {
+                    column: Some(name.into()),
+                    expected,
+                    actual,
+                }
+            }
+            other => other,
+        }
+    }
+}
+
+impl Error for ConversionError {
+    fn description(&self) -> &str {
+        match *self {
+            ConversionError::Null { .. } => "Unexpected NULL",
+            ConversionError::TypeMismatch { .. } => "Type mismatch",
+            ConversionError::NoSuchColumn { .. } => "No such column",
+            ConversionError::IndexOutOfRange { .. } => "Index out of range",
+        }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConversionError::Null { column: Some(ref column) } => {
+                write!(f, "Column '{}' is NULL", column)
+            }
+            ConversionError::Null { column: None } => write!(f, "Value is NULL"),
+            ConversionError::TypeMismatch {
+                column: Some(ref column),
+                ref expected,
+                ref actual,
+            } => write!(f, "Column '{}': expected {}, got {}", column, expected, actual),
+            ConversionError::TypeMismatch {
+                column: None,
+                ref expected,
+                ref actual,
+            } => write!(f, "Expected {}, got {}", expected, actual),
+            ConversionError::NoSuchColumn { ref column } => {
+                write!(f, "No such column '{}'", column)
+            }
+            ConversionError::IndexOutOfRange { index, len } => {
+                write!(f, "Index {} out of range for a row of {} columns", index, len)
+            }
+        }
+    }
+}
+
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct BackendError {
     pub description: String,
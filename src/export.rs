@@ -0,0 +1,341 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//!
+//! Writes query results as CSV, JSON Lines or an aligned text table, e.g. with
+//! [`RowIterator::write_csv`](../struct.RowIterator.html#method.write_csv).
+//!
+//! Arrays and objects are written as compact JSON in CSV and tables, and as
+//! themselves in JSON Lines.
+//!
+
+extern crate serde_json;
+
+use self::serde_json::Value;
+use columns::Columns;
+use row::Row;
+use std::io::{self, Write};
+
+///
+/// When CSV fields are enclosed in quotes.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quoting {
+    /// Only fields that contain the delimiter, the quote or a line break
+    Necessary,
+    /// Every field, including the header
+    Always,
+    /// Every field that isn't a number, a boolean or NULL
+    NonNumeric,
+}
+
+///
+/// How rows are written as CSV.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    /// Separates the fields of a record. Default: `,`
+    pub delimiter: char,
+
+    /// Encloses fields; quotes within fields are doubled. Default: `"`
+    pub quote: char,
+
+    /// Which fields are quoted. Default: `Quoting::Necessary`
+    pub quoting: Quoting,
+
+    /// Whether the first record holds the column names. Default: `true`
+    pub header: bool,
+
+    /// Written for NULL values. Default: an empty field
+    pub null: String,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions {
+            delimiter: ',',
+            quote: '"',
+            quoting: Quoting::Necessary,
+            header: true,
+            null: String::new(),
+        }
+    }
+}
+
+// A value as plain text: strings without quotes, containers as JSON.
+fn text(value: &Value, null: &str) -> String {
+    match *value {
+        Value::Null => null.to_string(),
+        Value::String(ref s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+fn csv_field<W: Write>(out: &mut W, field: &str, quote: bool, options: &CsvOptions) -> io::Result<()> {
+    let quote = quote ||
+                field.contains(|c| {
+                                   c == options.delimiter || c == options.quote || c == '\n' ||
+                                   c == '\r'
+                               });
+    if quote {
+        let q = options.quote.to_string();
+        write!(out, "{}{}{}", q, field.replace(&q, &q.repeat(2)), q)
+    } else {
+        write!(out, "{}", field)
+    }
+}
+
+fn csv_record<W, I>(out: &mut W, fields: I, options: &CsvOptions) -> io::Result<()>
+    where W: Write,
+          I: Iterator<Item = (String, bool)>
+{
+    for (i, (field, quote)) in fields.enumerate() {
+        if i > 0 {
+            write!(out, "{}", options.delimiter)?;
+        }
+        csv_field(out, &field, quote, options)?;
+    }
+    out.write_all(b"\r\n")
+}
+
+///
+/// Writes the rows as CSV (RFC 4180 line endings) and returns how many were
+/// written.
+///
+pub fn write_csv<W, I>(columns: &Columns, rows: I, mut out: W, options: &CsvOptions) -> io::Result<usize>
+    where W: Write,
+          I: IntoIterator<Item = Row>
+{
+    let always = options.quoting == Quoting::Always;
+    if options.header {
+        csv_record(&mut out,
+                   columns.names().iter().map(|name| (name.clone(), always)),
+                   options)?;
+    }
+    let mut count = 0;
+    for row in rows {
+        let fields = row.values().iter().map(|value| {
+            let quote = match options.quoting {
+                Quoting::Necessary => false,
+                Quoting::Always => true,
+                Quoting::NonNumeric => {
+                    !(value.is_null() || value.is_boolean() || value.is_number())
+                }
+            };
+            (text(value, &options.null), quote)
+        });
+        csv_record(&mut out, fields, options)?;
+        count += 1;
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+///
+/// Writes every row as a JSON object keyed by column name, one per line, and
+/// returns how many were written. Keys are in column order.
+///
+pub fn write_json_lines<W, I>(rows: I, mut out: W) -> io::Result<usize>
+    where W: Write,
+          I: IntoIterator<Item = Row>
+{
+    let mut count = 0;
+    for row in rows {
+        out.write_all(b"{")?;
+        for (i, (name, value)) in row.iter().enumerate() {
+            if i > 0 {
+                out.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut out, &name)?;
+            out.write_all(b":")?;
+            serde_json::to_writer(&mut out, value)?;
+        }
+        out.write_all(b"}\n")?;
+        count += 1;
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+// Tables show line breaks and tabs escaped, to keep one line per row.
+fn cell(value: &Value) -> String {
+    text(value, "NULL").replace('\n', "\\n").replace('\r', "\\r").replace('\t', "\\t")
+}
+
+fn table_line<W: Write>(out: &mut W,
+                        cells: &[String],
+                        widths: &[usize],
+                        right: &[bool])
+                        -> io::Result<()> {
+    let line: Vec<String> = cells.iter()
+        .zip(widths)
+        .zip(right)
+        .map(|((cell, &width), &right)| if right {
+                 format!("{:>1$}", cell, width)
+             } else {
+                 format!("{:<1$}", cell, width)
+             })
+        .collect();
+    writeln!(out, "| {} |", line.join(" | "))
+}
+
+///
+/// Writes the rows as a text table with aligned columns, numbers to the right,
+/// and returns how many were written. All rows are read before the first one
+/// is written, to size the columns.
+///
+/// ```text
+/// +----+-------+------------+
+/// | id | name  | tags       |
+/// +----+-------+------------+
+/// |  1 | hello | ["a","b"]  |
+/// |  2 | NULL  | []         |
+/// +----+-------+------------+
+/// ```
+pub fn write_table<W, I>(columns: &Columns, rows: I, mut out: W) -> io::Result<usize>
+    where W: Write,
+          I: IntoIterator<Item = Row>
+{
+    let mut header: Vec<String> = columns.names().to_vec();
+    let mut right = vec![true; header.len()];
+    let mut body: Vec<Vec<String>> = vec![];
+    for row in rows {
+        let values = row.values();
+        if values.len() > header.len() {
+            for i in header.len()..values.len() {
+                header.push(i.to_string());
+                right.push(true);
+            }
+        }
+        for (i, value) in values.iter().enumerate() {
+            right[i] &= value.is_number() || value.is_null();
+        }
+        body.push(values.iter().map(cell).collect());
+    }
+    for cells in &mut body {
+        cells.resize(header.len(), String::new());
+    }
+
+    let mut widths: Vec<usize> = header.iter().map(|name| name.chars().count()).collect();
+    for cells in &body {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let border: Vec<String> = widths.iter().map(|&width| "-".repeat(width + 2)).collect();
+    let border = format!("+{}+", border.join("+"));
+
+    writeln!(out, "{}", border)?;
+    table_line(&mut out, &header, &widths, &vec![false; header.len()])?;
+    writeln!(out, "{}", border)?;
+    for cells in &body {
+        table_line(&mut out, cells, &widths, &right)?;
+    }
+    writeln!(out, "{}", border)?;
+    out.flush()?;
+    Ok(body.len())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn result() -> (Columns, Vec<Row>) {
+        let columns = Arc::new(Columns::new(vec!["id".to_string(),
+                                                 "name".to_string(),
+                                                 "tags".to_string()],
+                                            None));
+        let rows = vec![vec![json!(1), json!("hello"), json!(["a", "b"])],
+                        vec![json!(2), json!(null), json!({"k": "v, w"})],
+                        vec![json!(3.5), json!("say \"hi\"\nbye"), json!(true)]];
+        let rows = rows.into_iter().map(|values| Row::with_columns(values, columns.clone())).collect();
+        ((*columns).clone(), rows)
+    }
+
+    #[test]
+    fn csv() {
+        let (columns, rows) = result();
+        let mut out = vec![];
+        assert_eq!(write_csv(&columns, rows, &mut out, &CsvOptions::default()).unwrap(), 3);
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "id,name,tags\r\n\
+                    1,hello,\"[\"\"a\"\",\"\"b\"\"]\"\r\n\
+                    2,,\"{\"\"k\"\":\"\"v, w\"\"}\"\r\n\
+                    3.5,\"say \"\"hi\"\"\nbye\",true\r\n");
+    }
+
+    #[test]
+    fn csv_options() {
+        let (columns, rows) = result();
+        let options = CsvOptions {
+            delimiter: ';',
+            quote: '\'',
+            quoting: Quoting::NonNumeric,
+            header: false,
+            null: "NULL".to_string(),
+        };
+        let mut out = vec![];
+        write_csv(&columns, rows.into_iter().take(2), &mut out, &options).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "1;'hello';'[\"a\",\"b\"]'\r\n2;NULL;'{\"k\":\"v, w\"}'\r\n");
+
+        let (columns, rows) = result();
+        let options = CsvOptions { quoting: Quoting::Always, ..CsvOptions::default() };
+        let mut out = vec![];
+        write_csv(&columns, rows.into_iter().take(1), &mut out, &options).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "\"id\",\"name\",\"tags\"\r\n\"1\",\"hello\",\"[\"\"a\"\",\"\"b\"\"]\"\r\n");
+    }
+
+    #[test]
+    fn json_lines() {
+        let (_, rows) = result();
+        let mut out = vec![];
+        assert_eq!(write_json_lines(rows, &mut out).unwrap(), 3);
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], r#"{"id":1,"name":"hello","tags":["a","b"]}"#);
+        assert_eq!(lines[1], r#"{"id":2,"name":null,"tags":{"k":"v, w"}}"#);
+        assert_eq!(serde_json::from_str::<Value>(lines[2]).unwrap(),
+                   json!({"id": 3.5, "name": "say \"hi\"\nbye", "tags": true}));
+    }
+
+    #[test]
+    fn table() {
+        let (columns, rows) = result();
+        let mut out = vec![];
+        assert_eq!(write_table(&columns, rows, &mut out).unwrap(), 3);
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "+-----+---------------+--------------+\n\
+                    | id  | name          | tags         |\n\
+                    +-----+---------------+--------------+\n\
+                    |   1 | hello         | [\"a\",\"b\"]    |\n\
+                    |   2 | NULL          | {\"k\":\"v, w\"} |\n\
+                    | 3.5 | say \"hi\"\\nbye | true         |\n\
+                    +-----+---------------+--------------+\n");
+    }
+
+    #[test]
+    fn empty() {
+        let columns = Columns::new(vec!["a".to_string()], None);
+        let mut out = vec![];
+        assert_eq!(write_table(&columns, vec![], &mut out).unwrap(), 0);
+        assert_eq!(String::from_utf8(out).unwrap(), "+---+\n| a |\n+---+\n+---+\n");
+        let mut out = vec![];
+        write_csv(&columns, vec![], &mut out, &CsvOptions::default()).unwrap();
+        assert_eq!(out, b"a\r\n");
+    }
+}
@@ -0,0 +1,345 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//!
+//! `geo-types` support for `geo_point` and `geo_shape` columns (`geo` feature).
+//!
+//! CrateDB returns a `geo_point` as a `[lon, lat]` array and a `geo_shape` as a
+//! GeoJSON object. Both read as any of the `geo-types` geometries, which also
+//! accept WKT strings (e.g. from `select 'POINT (9.74 47.41)'`).
+//!
+//! As parameters, `GeoPoint` sends a point as `[lon, lat]`, `GeoShape` sends
+//! any geometry as GeoJSON and `Wkt` as a WKT string.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use cratedb::geo::{GeoPoint, GeoShape};
+//! use geo_types::{point, Point, Polygon};
+//! c.query("insert into places (location, area) values (?, ?)",
+//!         Some(Box::new((GeoPoint(point!(x: 9.74, y: 47.41)), GeoShape(area)))))?;
+//! let location: Point<f64> = row.get("location")?;
+//! let area: Polygon<f64> = row.get("area")?;
+//! ```
+
+extern crate geo_types;
+extern crate serde;
+extern crate serde_json;
+extern crate wkt;
+
+use self::geo_types::{Coord, Geometry, GeometryCollection, LineString, MultiLineString, MultiPoint,
+                      MultiPolygon, Point, Polygon};
+use self::serde::{Serialize, Serializer};
+use self::serde_json::{Map, Value};
+use self::wkt::{ToWkt, TryFromWkt};
+use convert::{mismatch, FromValue};
+use error::ConversionError;
+use std::convert::TryFrom;
+
+fn geojson_error(description: &str) -> ConversionError {
+    ConversionError::mismatch("GeoJSON geometry", description)
+}
+
+fn coord(value: &Value) -> Result<Coord<f64>, ConversionError> {
+    match value.as_array().map(|c| c.iter().map(Value::as_f64).collect::<Vec<_>>()) {
+        Some(ref c) if c.len() >= 2 => {
+            match (c[0], c[1]) {
+                (Some(x), Some(y)) => Ok(Coord { x, y }),
+                _ => Err(geojson_error(&format!("invalid position {}", value))),
+            }
+        }
+        _ => Err(geojson_error(&format!("invalid position {}", value))),
+    }
+}
+
+fn array(value: &Value) -> Result<&Vec<Value>, ConversionError> {
+    value.as_array().ok_or_else(|| geojson_error(&format!("expected an array, got {}", value)))
+}
+
+fn line_string(value: &Value) -> Result<LineString<f64>, ConversionError> {
+    array(value)?.iter().map(coord).collect::<Result<Vec<_>, _>>().map(LineString)
+}
+
+fn polygon(value: &Value) -> Result<Polygon<f64>, ConversionError> {
+    let mut rings = array(value)?.iter().map(line_string).collect::<Result<Vec<_>, _>>()?;
+    if rings.is_empty() {
+        return Ok(Polygon::new(LineString(vec![]), vec![]));
+    }
+    let exterior = rings.remove(0);
+    Ok(Polygon::new(exterior, rings))
+}
+
+// Reads a GeoJSON geometry object.
+fn from_geojson(object: &Map<String, Value>) -> Result<Geometry<f64>, ConversionError> {
+    let kind = object.get("type").and_then(Value::as_str).unwrap_or("");
+    if kind == "GeometryCollection" {
+        let geometries = object.get("geometries")
+            .ok_or_else(|| geojson_error("GeometryCollection without geometries"))?;
+        return array(geometries)?
+                   .iter()
+                   .map(geometry)
+                   .collect::<Result<Vec<_>, _>>()
+                   .map(|g| Geometry::GeometryCollection(GeometryCollection(g)));
+    }
+    let coordinates = object.get("coordinates")
+        .ok_or_else(|| geojson_error(&format!("{} without coordinates", kind)))?;
+    match kind {
+        "Point" => coord(coordinates).map(|c| Geometry::Point(Point(c))),
+        "LineString" => line_string(coordinates).map(Geometry::LineString),
+        "Polygon" => polygon(coordinates).map(Geometry::Polygon),
+        "MultiPoint" => {
+            array(coordinates)?
+                .iter()
+                .map(|c| coord(c).map(Point))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|p| Geometry::MultiPoint(MultiPoint(p)))
+        }
+        "MultiLineString" => {
+            array(coordinates)?
+                .iter()
+                .map(line_string)
+                .collect::<Result<Vec<_>, _>>()
+                .map(|l| Geometry::MultiLineString(MultiLineString(l)))
+        }
+        "MultiPolygon" => {
+            array(coordinates)?
+                .iter()
+                .map(polygon)
+                .collect::<Result<Vec<_>, _>>()
+                .map(|p| Geometry::MultiPolygon(MultiPolygon(p)))
+        }
+        _ => Err(geojson_error(&format!("unknown type \"{}\"", kind))),
+    }
+}
+
+///
+/// Reads a `[lon, lat]` array, a GeoJSON geometry or a WKT string.
+///
+pub fn geometry(value: &Value) -> Result<Geometry<f64>, ConversionError> {
+    match *value {
+        Value::Array(_) => coord(value).map(|c| Geometry::Point(Point(c))),
+        Value::Object(ref object) => from_geojson(object),
+        Value::String(ref s) => {
+            Geometry::try_from_wkt_str(s)
+                .map_err(|e| ConversionError::mismatch("WKT geometry", format!("\"{}\" ({})", s, e)))
+        }
+        _ => Err(mismatch("geometry", value)),
+    }
+}
+
+fn positions(line: &LineString<f64>) -> Value {
+    Value::Array(line.0.iter().map(|c| json!([c.x, c.y])).collect())
+}
+
+fn rings(polygon: &Polygon<f64>) -> Value {
+    Value::Array(Some(polygon.exterior())
+                     .into_iter()
+                     .chain(polygon.interiors())
+                     .map(positions)
+                     .collect())
+}
+
+///
+/// The GeoJSON object for a geometry. `Line`s are written as `LineString`s,
+/// `Rect`s and `Triangle`s as `Polygon`s.
+///
+pub fn to_geojson(geometry: &Geometry<f64>) -> Value {
+    match *geometry {
+        Geometry::Point(ref p) => json!({"type": "Point", "coordinates": [p.x(), p.y()]}),
+        Geometry::Line(ref l) => {
+            to_geojson(&Geometry::LineString(LineString(vec![l.start, l.end])))
+        }
+        Geometry::LineString(ref l) => json!({"type": "LineString", "coordinates": positions(l)}),
+        Geometry::Polygon(ref p) => json!({"type": "Polygon", "coordinates": rings(p)}),
+        Geometry::MultiPoint(ref m) => {
+            let points: Vec<Value> = m.0.iter().map(|p| json!([p.x(), p.y()])).collect();
+            json!({"type": "MultiPoint", "coordinates": points})
+        }
+        Geometry::MultiLineString(ref m) => {
+            let lines: Vec<Value> = m.0.iter().map(positions).collect();
+            json!({"type": "MultiLineString", "coordinates": lines})
+        }
+        Geometry::MultiPolygon(ref m) => {
+            let polygons: Vec<Value> = m.0.iter().map(rings).collect();
+            json!({"type": "MultiPolygon", "coordinates": polygons})
+        }
+        Geometry::GeometryCollection(ref c) => {
+            let geometries: Vec<Value> = c.0.iter().map(to_geojson).collect();
+            json!({"type": "GeometryCollection", "geometries": geometries})
+        }
+        Geometry::Rect(ref r) => to_geojson(&Geometry::Polygon(r.to_polygon())),
+        Geometry::Triangle(ref t) => to_geojson(&Geometry::Polygon(t.to_polygon())),
+    }
+}
+
+// The name of a geometry's type, for error messages.
+fn kind(geometry: &Geometry<f64>) -> &'static str {
+    match *geometry {
+        Geometry::Point(_) => "Point",
+        Geometry::Line(_) => "Line",
+        Geometry::LineString(_) => "LineString",
+        Geometry::Polygon(_) => "Polygon",
+        Geometry::MultiPoint(_) => "MultiPoint",
+        Geometry::MultiLineString(_) => "MultiLineString",
+        Geometry::MultiPolygon(_) => "MultiPolygon",
+        Geometry::GeometryCollection(_) => "GeometryCollection",
+        Geometry::Rect(_) => "Rect",
+        Geometry::Triangle(_) => "Triangle",
+    }
+}
+
+impl FromValue for Geometry<f64> {
+    fn from_value(value: &Value) -> Result<Geometry<f64>, ConversionError> {
+        geometry(value)
+    }
+}
+
+macro_rules! from_geometry {
+    ($($t:ident),*) => {
+        $(
+        impl FromValue for $t<f64> {
+            fn from_value(value: &Value) -> Result<$t<f64>, ConversionError> {
+                let geometry = geometry(value)?;
+                let actual = kind(&geometry);
+                $t::try_from(geometry).map_err(|_| ConversionError::mismatch(stringify!($t), actual))
+            }
+        }
+        )*
+    }
+}
+
+from_geometry!(Point, LineString, Polygon, MultiPoint, MultiLineString, MultiPolygon);
+
+///
+/// Sends a point as a `[lon, lat]` array, e.g. for a `geo_point` column.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint(pub Point<f64>);
+
+impl Serialize for GeoPoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        [self.0.x(), self.0.y()].serialize(serializer)
+    }
+}
+
+///
+/// Sends a geometry as a GeoJSON object, e.g. for a `geo_shape` column.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoShape<G>(pub G);
+
+impl<G: Clone + Into<Geometry<f64>>> Serialize for GeoShape<G> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        to_geojson(&self.0.clone().into()).serialize(serializer)
+    }
+}
+
+///
+/// Sends a geometry as a WKT string, which both `geo_point` and `geo_shape`
+/// columns accept.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wkt<G>(pub G);
+
+impl<G: ToWkt<f64>> Serialize for Wkt<G> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.wkt_string())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::geo_types::{line_string, point, polygon, Rect};
+
+    fn triangle() -> Polygon<f64> {
+        polygon![(x: 0.0, y: 0.0), (x: 10.0, y: 0.0), (x: 10.0, y: 10.0), (x: 0.0, y: 0.0)]
+    }
+
+    #[test]
+    fn points() {
+        let p = point!(x: 9.74, y: 47.41);
+        assert_eq!(Point::from_value(&json!([9.74, 47.41])), Ok(p));
+        assert_eq!(Point::from_value(&json!("POINT (9.74 47.41)")), Ok(p));
+        assert_eq!(Point::from_value(&json!({"type": "Point", "coordinates": [9.74, 47.41]})),
+                   Ok(p));
+        assert_eq!(Option::<Point<f64>>::from_value(&Value::Null), Ok(None));
+    }
+
+    #[test]
+    fn geojson() {
+        let shape = json!({
+            "type": "Polygon",
+            "coordinates": [[[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 0.0]],
+                            [[1.0, 1.0], [2.0, 1.0], [2.0, 2.0], [1.0, 1.0]]]
+        });
+        let polygon = Polygon::from_value(&shape).unwrap();
+        assert_eq!(polygon.exterior(), triangle().exterior());
+        assert_eq!(polygon.interiors().len(), 1);
+        assert_eq!(to_geojson(&Geometry::Polygon(polygon)), shape);
+
+        let lines = json!({"type": "MultiLineString", "coordinates": [[[1.0, 2.0], [3.0, 4.0]]]});
+        assert_eq!(MultiLineString::from_value(&lines),
+                   Ok(MultiLineString(vec![line_string![(x: 1.0, y: 2.0), (x: 3.0, y: 4.0)]])));
+        let points = json!({"type": "MultiPoint", "coordinates": [[1.0, 2.0], [3.0, 4.0]]});
+        assert_eq!(to_geojson(&Geometry::from_value(&points).unwrap()), points);
+        let collection = json!({
+            "type": "GeometryCollection",
+            "geometries": [{"type": "Point", "coordinates": [1.0, 2.0]}, lines]
+        });
+        assert_eq!(to_geojson(&Geometry::from_value(&collection).unwrap()), collection);
+        let multi = json!({"type": "MultiPolygon", "coordinates": [shape["coordinates"]]});
+        assert_eq!(MultiPolygon::from_value(&multi).unwrap().0.len(), 1);
+    }
+
+    #[test]
+    fn wkt() {
+        assert_eq!(Polygon::from_value(&json!("POLYGON ((0 0, 10 0, 10 10, 0 0))")),
+                   Ok(triangle()));
+        assert_eq!(LineString::from_value(&json!("LINESTRING (1 2, 3 4)")),
+                   Ok(line_string![(x: 1.0, y: 2.0), (x: 3.0, y: 4.0)]));
+        let multi = MultiPolygon::from_value(&json!("MULTIPOLYGON (((0 0, 10 0, 10 10, 0 0)))"))
+            .unwrap();
+        assert_eq!(multi.0, vec![triangle()]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Point::from_value(&json!("POLYGON ((0 0, 10 0, 10 10, 0 0))")),
+                   Err(ConversionError::mismatch("Point", "Polygon")));
+        assert_eq!(Point::from_value(&json!([1.0])),
+                   Err(ConversionError::mismatch("GeoJSON geometry", "invalid position [1.0]")));
+        assert_eq!(Polygon::from_value(&json!({"type": "Circle", "coordinates": []})),
+                   Err(ConversionError::mismatch("GeoJSON geometry", "unknown type \"Circle\"")));
+        assert!(Point::from_value(&json!("POINT (1")).is_err());
+        assert_eq!(Point::from_value(&json!(true)),
+                   Err(ConversionError::mismatch("geometry", "boolean")));
+        assert_eq!(Polygon::from_value(&Value::Null),
+                   Err(ConversionError::Null { column: None }));
+    }
+
+    #[test]
+    fn parameters() {
+        let rect = Rect::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 1.0, y: 1.0 });
+        assert_eq!(serde_json::to_value((GeoPoint(point!(x: 9.74, y: 47.41)),
+                                         GeoShape(line_string![(x: 1.0, y: 2.0), (x: 3.0, y: 4.0)]),
+                                         Wkt(point!(x: 1.0, y: 2.0))))
+                       .unwrap(),
+                   json!([[9.74, 47.41],
+                          {"type": "LineString", "coordinates": [[1.0, 2.0], [3.0, 4.0]]},
+                          "POINT(1 2)"]));
+        assert_eq!(serde_json::to_value(GeoShape(rect)).unwrap()["type"], "Polygon");
+    }
+}
@@ -0,0 +1,330 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//
+// Tracing spans and metrics for SQL and blob operations. The spans compile to
+// (almost) nothing unless the `tracing` feature is enabled.
+//
+
+extern crate serde_json;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+use self::serde_json::Value;
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+#[cfg(feature = "tracing")]
+use common::to_hex_string;
+use error::{BlobError, CrateDBError};
+use metrics::{self, MetricsSink, Observation};
+
+///
+/// A running operation against the cluster. Its span is entered until the
+/// operation finishes.
+///
+pub struct Operation {
+    kind: metrics::Operation,
+    node: Option<String>,
+    started: Instant,
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+}
+
+///
+/// An error that can be counted by its code.
+///
+pub trait ErrorCode {
+    fn error_code(&self) -> String;
+}
+
+impl ErrorCode for CrateDBError {
+    fn error_code(&self) -> String {
+        self.code.clone()
+    }
+}
+
+impl ErrorCode for BlobError {
+    fn error_code(&self) -> String {
+        match *self {
+            BlobError::Action(ref e) => e.code.clone(),
+            BlobError::Transport(_) => "transport".to_string(),
+        }
+    }
+}
+
+///
+/// The node of an endpoint URL, i.e. without the "_sql" or "_blobs" path.
+///
+pub fn node(endpoint: &str) -> String {
+    match endpoint.rfind('/') {
+        Some(i) => endpoint[..=i].to_string(),
+        None => endpoint.to_string(),
+    }
+}
+
+///
+/// Starts a SQL operation on the node (its endpoint URL).
+///
+pub fn query(node: Option<&str>,
+             statement: &str,
+             params: Option<&Value>,
+             bulk: bool,
+             log_parameters: bool)
+             -> Operation {
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!("cratedb.query",
+                                    node = node.unwrap_or("none"),
+                                    statement,
+                                    params = %describe_params(params, bulk, log_parameters),
+                                    bulk)
+            .entered();
+    #[cfg(not(feature = "tracing"))]
+    let _ = (statement, params, log_parameters);
+    Operation {
+        kind: if bulk {
+            metrics::Operation::BulkQuery
+        } else {
+            metrics::Operation::Query
+        },
+        node: node.map(self::node),
+        started: Instant::now(),
+        #[cfg(feature = "tracing")]
+        span,
+    }
+}
+
+///
+/// Starts a blob operation (upload, fetch or delete) on the node.
+///
+pub fn blob(kind: metrics::Operation,
+            node: Option<&str>,
+            table: &str,
+            sha1: &[u8])
+            -> Operation {
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!("cratedb.blob",
+                                    operation = kind.as_str(),
+                                    node = node.unwrap_or("none"),
+                                    table,
+                                    digest = %to_hex_string(sha1))
+            .entered();
+    #[cfg(not(feature = "tracing"))]
+    let _ = (table, sha1);
+    Operation {
+        kind,
+        node: node.map(self::node),
+        started: Instant::now(),
+        #[cfg(feature = "tracing")]
+        span,
+    }
+}
+
+impl Operation {
+    ///
+    /// Ends the operation with its result and, for SQL, the duration the server
+    /// reported (in ms), and reports it to the metrics sink.
+    ///
+    pub fn finish<T, E: Debug + ErrorCode>(self,
+                                           result: &Result<T, E>,
+                                           server_duration: Option<f64>,
+                                           metrics: &dyn MetricsSink) {
+        let elapsed = self.started.elapsed();
+        metrics.observe(&Observation {
+                            operation: self.kind,
+                            node: self.node,
+                            error_code: result.as_ref().err().map(ErrorCode::error_code),
+                            elapsed,
+                            server_duration,
+                        });
+        let elapsed_ms = millis(elapsed);
+        #[cfg(feature = "tracing")]
+        {
+            match *result {
+                Ok(_) => {
+                    tracing::debug!(elapsed_ms,
+                                    server_duration_ms = server_duration.unwrap_or(-1.0),
+                                    "succeeded")
+                }
+                Err(ref error) => tracing::warn!(elapsed_ms, ?error, "failed"),
+            }
+            self.span.exit();
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = (result, server_duration, elapsed_ms);
+    }
+}
+
+///
+/// A duration in (fractional) milliseconds, the unit CrateDB reports.
+///
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0
+}
+
+// Parameters are replaced by their number (or that of the parameter sets for
+// bulk operations), unless logging them is enabled explicitly.
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+fn describe_params(params: Option<&Value>, bulk: bool, log_parameters: bool) -> String {
+    match params {
+        None => "none".to_string(),
+        Some(params) if log_parameters => params.to_string(),
+        Some(Value::Array(values)) if bulk => format!("<{} redacted sets>", values.len()),
+        Some(Value::Array(values)) => format!("<{} redacted>", values.len()),
+        Some(_) => "<redacted>".to_string(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::BackendError;
+    use metrics::InMemoryMetrics;
+    use std::time::Duration;
+
+    #[test]
+    fn redacted_params() {
+        let params = json!(["alice", "secret"]);
+        assert_eq!(describe_params(None, false, false), "none");
+        assert_eq!(describe_params(Some(&params), false, false), "<2 redacted>");
+        assert_eq!(describe_params(Some(&json!([[1], [2], [3]])), true, false),
+                   "<3 redacted sets>");
+        assert_eq!(describe_params(Some(&json!({"a": 1})), false, false),
+                   "<redacted>");
+        assert_eq!(describe_params(Some(&params), false, true),
+                   "[\"alice\",\"secret\"]");
+    }
+
+    #[test]
+    fn nodes() {
+        assert_eq!(node("http://db:4200/_sql"), "http://db:4200/");
+        assert_eq!(node("https://db/_blobs"), "https://db/");
+    }
+
+    #[test]
+    fn observations() {
+        let metrics = InMemoryMetrics::new();
+        query(Some("http://db:4200/_sql"), "select 1", None, false, false)
+            .finish(&Ok::<(), CrateDBError>(()), Some(1.5), &metrics);
+        query(None, "insert", Some(&json!([[1]])), true, false)
+            .finish(&Err::<(), _>(CrateDBError::new("no table", "4041")), None, &metrics);
+        blob(metrics::Operation::Fetch, None, "b", &[1])
+            .finish(&Err::<(), _>(BlobError::Transport(BackendError::new("refused".to_string()))),
+                    None,
+                    &metrics);
+
+        let observations = metrics.observations();
+        assert_eq!(observations[0].operation, metrics::Operation::Query);
+        assert_eq!(observations[0].node, Some("http://db:4200/".to_string()));
+        assert_eq!(observations[0].error_code, None);
+        assert_eq!(observations[0].server_duration, Some(1.5));
+        assert_eq!(observations[1].operation, metrics::Operation::BulkQuery);
+        assert_eq!(observations[1].error_code, Some("4041".to_string()));
+        assert_eq!(observations[2].operation, metrics::Operation::Fetch);
+        assert_eq!(observations[2].error_code, Some("transport".to_string()));
+    }
+
+    #[test]
+    fn milliseconds() {
+        assert_eq!(millis(Duration::new(1, 500_000)), 1000.5);
+    }
+
+    #[cfg(feature = "tracing")]
+    mod spans {
+        extern crate tracing;
+
+        use super::super::*;
+        use metrics::NoopMetrics;
+        use self::tracing::{Event, Id, Metadata, Subscriber};
+        use self::tracing::field::{Field, Visit};
+        use self::tracing::span::{Attributes, Record};
+        use std::fmt;
+        use std::sync::{Arc, Mutex};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Collects spans and events as "name field=value ..." lines.
+        #[derive(Clone, Default)]
+        struct Capture {
+            lines: Arc<Mutex<Vec<String>>>,
+            ids: Arc<AtomicUsize>,
+        }
+
+        struct Line(String);
+
+        impl Visit for Line {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                self.0.push_str(&format!(" {}={:?}", field.name(), value));
+            }
+        }
+
+        impl Subscriber for Capture {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes) -> Id {
+                let mut line = Line(span.metadata().name().to_string());
+                span.record(&mut line);
+                self.lines.lock().unwrap().push(line.0);
+                Id::from_u64(self.ids.fetch_add(1, Ordering::SeqCst) as u64 + 1)
+            }
+            fn record(&self, _: &Id, _: &Record) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, event: &Event) {
+                let mut line = Line(event.metadata().level().to_string());
+                event.record(&mut line);
+                self.lines.lock().unwrap().push(line.0);
+            }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        fn captured<F: FnOnce()>(f: F) -> Vec<String> {
+            let capture = Capture::default();
+            tracing::subscriber::with_default(capture.clone(), f);
+            let lines = capture.lines.lock().unwrap().clone();
+            lines
+        }
+
+        #[test]
+        fn query_span() {
+            let lines = captured(|| {
+                let op = query(Some("http://db:4200/_sql"),
+                               "select ?",
+                               Some(&json!(["secret"])),
+                               false,
+                               false);
+                op.finish(&Ok::<(), CrateDBError>(()), Some(1.5), &NoopMetrics);
+            });
+            assert_eq!(lines[0],
+                       "cratedb.query node=\"http://db:4200/_sql\" statement=\"select ?\" \
+                        params=<1 redacted> bulk=false");
+            assert!(lines[1].starts_with("DEBUG message=succeeded elapsed_ms="));
+            assert!(lines[1].ends_with("server_duration_ms=1.5"));
+        }
+
+        #[test]
+        fn blob_span() {
+            let lines = captured(|| {
+                let op = blob(metrics::Operation::Upload, None, "b", &[0xab, 0xcd]);
+                op.finish(&Err::<(), _>(CrateDBError::new("connection refused", "500")),
+                          None,
+                          &NoopMetrics);
+            });
+            assert_eq!(lines[0],
+                       "cratedb.blob operation=\"upload\" node=\"none\" table=\"b\" digest=abcd");
+            assert!(lines[1].starts_with("WARN message=failed elapsed_ms="));
+            assert!(lines[1].contains("message: \"connection refused\""));
+        }
+    }
+}
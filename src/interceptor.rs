@@ -0,0 +1,236 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate serde_json;
+
+use self::serde_json::Value;
+use error::CrateDBError;
+use rowiterator::RowIterator;
+
+///
+/// A SQL statement on its way to the cluster.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    /// The statement text
+    pub stmt: String,
+
+    /// The parameters (`args`), or parameter sets (`bulk_args`) of a bulk query
+    pub params: Option<Value>,
+
+    /// Whether this is a bulk query
+    pub bulk: bool,
+}
+
+///
+/// What a statement returned, as an interceptor's `after` sees it.
+///
+#[derive(Debug, Clone, Copy)]
+pub enum Outcome<'a> {
+    /// The rows of a query, which `RowIterator::iter` reads without consuming
+    Rows(&'a RowIterator),
+    /// The row counts of a bulk query, one per parameter set
+    RowCounts(&'a [i64]),
+}
+
+///
+/// Sees every statement a `DBCluster` runs before it is sent, and its result
+/// afterwards. Interceptors run in the order they were added, `after` in
+/// reverse order.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use cratedb::error::CrateDBError;
+/// use cratedb::interceptor::Statement;
+/// c.add_interceptor(|s: &mut Statement| {
+///     if s.stmt.to_lowercase().starts_with("drop table") {
+///         return Err(CrateDBError::new("DROP TABLE is not allowed", "4030"));
+///     }
+///     s.stmt = format!("/* service=reports */ {}", s.stmt);
+///     Ok(())
+/// });
+/// ```
+pub trait Interceptor: Send + Sync {
+    ///
+    /// Called before the statement is sent. It may be rewritten, and returning
+    /// an error stops it from being sent at all; the error is returned from the
+    /// query instead.
+    ///
+    fn before(&self, statement: &mut Statement) -> Result<(), CrateDBError>;
+
+    ///
+    /// Called with the statement as it was sent and its result: the duration
+    /// the server reported with the rows or row counts, or the error. The
+    /// result is borrowed, so interceptors can read but not change it.
+    ///
+    fn after(&self, _statement: &Statement, _result: Result<(f64, Outcome), &CrateDBError>) {}
+}
+
+impl<F> Interceptor for F
+    where F: Fn(&mut Statement) -> Result<(), CrateDBError> + Send + Sync
+{
+    fn before(&self, statement: &mut Statement) -> Result<(), CrateDBError> {
+        self(statement)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate hyper;
+
+    use super::*;
+    use self::hyper::Url;
+    use dbcluster::DBCluster;
+    use sql::QueryRunner;
+    use std::sync::{Arc, Mutex};
+    use testing::{MockBackend, MockResponse};
+    use NoParams;
+
+    // Records what it sees as "before:..." and "after:..." lines.
+    struct Audit {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Interceptor for Audit {
+        fn before(&self, statement: &mut Statement) -> Result<(), CrateDBError> {
+            self.log.lock().unwrap().push(format!("{} before: {}", self.name, statement.stmt));
+            Ok(())
+        }
+
+        fn after(&self, statement: &Statement, result: Result<(f64, Outcome), &CrateDBError>) {
+            let outcome = match result {
+                Ok((duration, Outcome::Rows(rows))) => format!("{} {} rows", duration, rows.len()),
+                Ok((duration, Outcome::RowCounts(counts))) => format!("{} {:?}", duration, counts),
+                Err(e) => e.code.clone(),
+            };
+            self.log.lock().unwrap().push(format!("{} after: {} {}", self.name, statement.stmt, outcome));
+        }
+    }
+
+    fn cluster(backend: &MockBackend) -> DBCluster<MockBackend> {
+        DBCluster::with_custom_backend(vec![Url::parse("http://db:4200").unwrap()], backend.clone())
+    }
+
+    #[test]
+    fn rewrite_and_block() {
+        let backend = MockBackend::new();
+        backend.on("/* app */ select 1", MockResponse::rows(vec!["1"], vec![vec![json!(1)]]));
+        let mut c = cluster(&backend);
+        c.add_interceptor(|s: &mut Statement| {
+            if s.stmt.to_lowercase().starts_with("drop table") {
+                return Err(CrateDBError::new("DROP TABLE is not allowed", "4030"));
+            }
+            s.stmt = format!("/* app */ {}", s.stmt);
+            Ok(())
+        });
+
+        assert!(c.query("select 1", None::<Box<NoParams>>).is_ok());
+        let e = c.query("DROP TABLE t", None::<Box<NoParams>>).err().unwrap();
+        assert_eq!(e.code, "4030");
+        assert_eq!(backend.statements(), vec!["/* app */ select 1"]);
+    }
+
+    #[test]
+    fn params() {
+        let backend = MockBackend::new();
+        backend.on_params("insert into t values (?)", &json!([[1], [2], [3]]), MockResponse::bulk(vec![1, 1, 1]));
+        let mut c = cluster(&backend);
+        c.add_interceptor(|s: &mut Statement| {
+            assert!(s.bulk);
+            if let Some(Value::Array(ref mut sets)) = s.params {
+                sets.push(json!([3]));
+            }
+            Ok(())
+        });
+        let (_, counts) = c.bulk_query("insert into t values (?)", Box::new(json!([[1], [2]])))
+            .unwrap();
+        assert_eq!(counts, vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn order() {
+        let backend = MockBackend::new();
+        backend.on("select 1", MockResponse::rows(vec!["1"], vec![]).with_duration(2.0));
+        backend.on("select x", MockResponse::error(4043, "Column x unknown"));
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut c = cluster(&backend);
+        c.add_interceptor(Audit { name: "outer", log: log.clone() });
+        c.add_interceptor(Audit { name: "inner", log: log.clone() });
+
+        c.query("select 1", None::<Box<NoParams>>).unwrap();
+        assert!(c.query("select x", None::<Box<NoParams>>).is_err());
+        assert_eq!(*log.lock().unwrap(),
+                   vec!["outer before: select 1",
+                        "inner before: select 1",
+                        "inner after: select 1 2 0 rows",
+                        "outer after: select 1 2 0 rows",
+                        "outer before: select x",
+                        "inner before: select x",
+                        "inner after: select x 4043",
+                        "outer after: select x 4043"]);
+    }
+
+    #[test]
+    fn results() {
+        let backend = MockBackend::new();
+        backend.on("select name from t",
+                   MockResponse::rows(vec!["name"], vec![vec![json!("a")], vec![json!("b")]]));
+        backend.on("insert into t values (?)", MockResponse::bulk(vec![1, 0]));
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut c = cluster(&backend);
+        c.add_interceptor(Audit { name: "audit", log: log.clone() });
+
+        struct Names(Arc<Mutex<Vec<String>>>);
+        impl Interceptor for Names {
+            fn before(&self, _: &mut Statement) -> Result<(), CrateDBError> {
+                Ok(())
+            }
+
+            fn after(&self, _: &Statement, result: Result<(f64, Outcome), &CrateDBError>) {
+                if let Ok((_, Outcome::Rows(rows))) = result {
+                    let mut names = self.0.lock().unwrap();
+                    names.extend(rows.iter().map(|row| row.get::<String, _>("name").unwrap()));
+                }
+            }
+        }
+        let names = Arc::new(Mutex::new(vec![]));
+        c.add_interceptor(Names(names.clone()));
+
+        let (_, rows) = c.query("select name from t", None::<Box<NoParams>>).unwrap();
+        assert_eq!(*names.lock().unwrap(), vec!["a", "b"]);
+        assert_eq!(rows.len(), 2);
+        c.bulk_query("insert into t values (?)", Box::new(json!([[1], [2]]))).unwrap();
+        assert_eq!(*log.lock().unwrap(),
+                   vec!["audit before: select name from t",
+                        "audit after: select name from t 0 2 rows",
+                        "audit before: insert into t values (?)",
+                        "audit after: insert into t values (?) 0 [1, 0]"]);
+    }
+
+    #[test]
+    fn blocked_statements_skip_the_rest() {
+        let backend = MockBackend::new();
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut c = cluster(&backend);
+        c.add_interceptor(|_: &mut Statement| Err(CrateDBError::new("read-only", "4030")));
+        c.add_interceptor(Audit { name: "audit", log: log.clone() });
+
+        assert!(c.query("delete from t", None::<Box<NoParams>>).is_err());
+        assert!(log.lock().unwrap().is_empty());
+        assert!(backend.calls().is_empty());
+    }
+}
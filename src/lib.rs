@@ -20,17 +20,35 @@ extern crate serde_json;
 extern crate serde_derive;
 
 pub mod error;
+pub mod config;
 pub mod row;
+pub mod convert;
+pub mod columns;
+pub mod export;
+#[cfg(feature = "chrono")]
+pub mod datetime;
+#[cfg(feature = "geo")]
+pub mod geo;
+#[cfg(feature = "arrow")]
+pub mod arrow;
 pub mod blob;
 pub mod dbcluster;
 pub mod sql;
+pub mod backend;
+pub mod metrics;
+pub mod interceptor;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod rowiterator;
-mod backend;
 mod common;
+mod tls;
+mod proxy;
+mod instrument;
 
 
 use dbcluster::DBCluster;
 use backend::DefaultHTTPBackend;
+pub use rowiterator::RowIterator;
 
 pub type Cluster = DBCluster<DefaultHTTPBackend>;
 pub type NoParams = sql::Nothing;
@@ -39,9 +57,13 @@ pub type NoParams = sql::Nothing;
 pub type Nothing = NoParams;
 
 #[cfg(test)]
+// These tests predate the lints below and are kept as they were written.
+#[allow(deprecated, dead_code, unused_imports, unused_variables, bare_trait_objects,
+        clippy::approx_constant, clippy::get_first, clippy::redundant_field_names,
+        clippy::single_match, clippy::useless_vec)]
 mod tests {
     extern crate hex;
-    use super::Nothing;
+    use super::{NoParams, Nothing};
     use backend::{Backend, BackendResult};
     use sql::QueryRunner;
     use blob::{BlobContainer, BlobRef};
@@ -101,7 +123,7 @@ mod tests {
                       to: Option<String>,
                       bucket: &str,
                       sha1: &[u8])
-                      -> Result<(BackendResult, Box<Read>), BackendError> {
+                      -> Result<(BackendResult, Box<Read + Send>), BackendError> {
             Err(self.failure.clone())
         }
     }
@@ -181,7 +203,7 @@ mod tests {
                       to: Option<String>,
                       bucket: &str,
                       sha1: &[u8])
-                      -> Result<(BackendResult, Box<Read>), BackendError> {
+                      -> Result<(BackendResult, Box<Read + Send>), BackendError> {
             let sha1_v = sha1.to_vec();
             match self.result {
                 BackendResult::Ok => {
@@ -224,6 +246,49 @@ mod tests {
         c: f64,
     }
 
+    fn assert_send<T: Send>() {}
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn thread_safety() {
+        assert_send_sync::<super::Cluster>();
+        assert_send_sync::<::backend::DefaultHTTPBackend>();
+        assert_send_sync::<DBCluster<::testing::MockBackend>>();
+        assert_send_sync::<::testing::ReplayBackend>();
+        assert_send_sync::<::testing::RecordingBackend<::testing::MockBackend>>();
+        assert_send_sync::<Row>();
+        assert_send_sync::<::rowiterator::RowIterator>();
+        assert_send_sync::<BlobRef>();
+        assert_send_sync::<CrateDBError>();
+        assert_send_sync::<BackendError>();
+        assert_send_sync::<BlobError>();
+        assert_send_sync::<::error::CrateDBConfigurationError>();
+        assert_send_sync::<::config::ClusterConfig>();
+        assert_send_sync::<::metrics::InMemoryMetrics>();
+        assert_send::<Box<dyn Read + Send>>();
+    }
+
+    #[test]
+    fn shared_cluster() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let backend = ::testing::MockBackend::new();
+        backend.on("select 1", ::testing::MockResponse::rows(vec!["1"], vec![vec![json!(1)]]));
+        let c = Arc::new(DBCluster::with_custom_backend(vec![], backend.clone()));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                     let c = c.clone();
+                     thread::spawn(move || c.query("select 1", None::<Box<NoParams>>).unwrap().1)
+                 })
+            .collect();
+        for handle in handles {
+            let mut rows = handle.join().unwrap();
+            assert_eq!(rows.next().unwrap().as_i64(0), Some(1));
+        }
+        assert_eq!(backend.statements().len(), 4);
+    }
+
 
     #[test]
     fn blob_upload() {
@@ -381,7 +446,7 @@ mod tests {
                                                      bucket),
                                              vec![],
                                              BackendResult::NotFound);
-        let error = cluster.list(bucket.as_ref()).unwrap_err();
+        let error = cluster.list(bucket.as_str()).unwrap_err();
         match error {
             BlobError::Action(crate_error) => {
                 assert_eq!(crate_error.message,
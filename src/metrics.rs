@@ -0,0 +1,341 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+///
+/// The kind of operation that was run against the cluster.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Query,
+    BulkQuery,
+    Upload,
+    Fetch,
+    Delete,
+}
+
+impl Operation {
+    ///
+    /// A label for the operation, e.g. "bulk_query".
+    ///
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Operation::Query => "query",
+            Operation::BulkQuery => "bulk_query",
+            Operation::Upload => "upload",
+            Operation::Fetch => "fetch",
+            Operation::Delete => "delete",
+        }
+    }
+}
+
+///
+/// A finished operation, as reported to a `MetricsSink`.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    /// What was run
+    pub operation: Operation,
+
+    /// The node it ran on (e.g. "http://localhost:4200/"), if there was one
+    pub node: Option<String>,
+
+    /// The error code (CrateDB's or the HTTP status), `None` on success.
+    /// Transport failures of BLOB operations are reported as "transport".
+    pub error_code: Option<String>,
+
+    /// The wall-clock duration of the round trip
+    pub elapsed: Duration,
+
+    /// The duration the server reported for SQL statements (in ms)
+    pub server_duration: Option<f64>,
+}
+
+///
+/// Receives an `Observation` for every query and BLOB operation of a
+/// `DBCluster`, as well as the BLOB bytes sent and received. Implementations
+/// are expected to be cheap, since they run on the caller's thread.
+///
+pub trait MetricsSink: Send + Sync {
+    ///
+    /// Called when an operation has finished.
+    ///
+    fn observe(&self, observation: &Observation);
+
+    ///
+    /// Called with the size of a successfully uploaded BLOB.
+    ///
+    fn bytes_uploaded(&self, _node: Option<&str>, _bytes: u64) {}
+
+    ///
+    /// Called with the number of bytes that were read from a fetched BLOB,
+    /// once its reader is dropped.
+    ///
+    fn bytes_downloaded(&self, _node: Option<&str>, _bytes: u64) {}
+}
+
+///
+/// Discards everything, the default of a `DBCluster`.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopMetrics;
+
+impl MetricsSink for NoopMetrics {
+    fn observe(&self, _observation: &Observation) {}
+}
+
+#[derive(Default)]
+struct Recorded {
+    observations: Vec<Observation>,
+    uploaded: u64,
+    downloaded: u64,
+}
+
+///
+/// Keeps all observations in memory, for tests and debugging.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use std::sync::Arc;
+/// use cratedb::metrics::{InMemoryMetrics, Operation};
+/// let metrics = Arc::new(InMemoryMetrics::new());
+/// c.set_metrics_sink(metrics.clone());
+/// c.query("select 1", None::<Box<NoParams>>).unwrap();
+/// assert_eq!(metrics.count(Operation::Query), 1);
+/// ```
+#[derive(Default)]
+pub struct InMemoryMetrics {
+    recorded: Mutex<Recorded>,
+}
+
+impl InMemoryMetrics {
+    pub fn new() -> InMemoryMetrics {
+        InMemoryMetrics::default()
+    }
+
+    fn recorded(&self) -> MutexGuard<'_, Recorded> {
+        self.recorded.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    ///
+    /// All observations, oldest first.
+    ///
+    pub fn observations(&self) -> Vec<Observation> {
+        self.recorded().observations.clone()
+    }
+
+    ///
+    /// The number of operations of this kind.
+    ///
+    pub fn count(&self, operation: Operation) -> usize {
+        self.recorded()
+            .observations
+            .iter()
+            .filter(|o| o.operation == operation)
+            .count()
+    }
+
+    ///
+    /// The number of operations per node.
+    ///
+    pub fn by_node(&self) -> HashMap<String, usize> {
+        let mut nodes = HashMap::new();
+        for o in &self.recorded().observations {
+            if let Some(ref node) = o.node {
+                *nodes.entry(node.clone()).or_insert(0) += 1;
+            }
+        }
+        nodes
+    }
+
+    ///
+    /// The number of failed operations per error code.
+    ///
+    pub fn errors(&self) -> HashMap<String, usize> {
+        let mut errors = HashMap::new();
+        for o in &self.recorded().observations {
+            if let Some(ref code) = o.error_code {
+                *errors.entry(code.clone()).or_insert(0) += 1;
+            }
+        }
+        errors
+    }
+
+    ///
+    /// The total size of all uploaded BLOBs.
+    ///
+    pub fn uploaded(&self) -> u64 {
+        self.recorded().uploaded
+    }
+
+    ///
+    /// The total number of bytes read from fetched BLOBs.
+    ///
+    pub fn downloaded(&self) -> u64 {
+        self.recorded().downloaded
+    }
+
+    ///
+    /// Forgets everything recorded so far.
+    ///
+    pub fn clear(&self) {
+        *self.recorded() = Recorded::default();
+    }
+}
+
+impl MetricsSink for InMemoryMetrics {
+    fn observe(&self, observation: &Observation) {
+        self.recorded().observations.push(observation.clone());
+    }
+
+    fn bytes_uploaded(&self, _node: Option<&str>, bytes: u64) {
+        self.recorded().uploaded += bytes;
+    }
+
+    fn bytes_downloaded(&self, _node: Option<&str>, bytes: u64) {
+        self.recorded().downloaded += bytes;
+    }
+}
+
+// Counts the bytes read from a fetched BLOB and reports them when dropped.
+pub(crate) struct CountingReader {
+    inner: Box<dyn Read + Send>,
+    node: Option<String>,
+    bytes: u64,
+    sink: Arc<dyn MetricsSink>,
+}
+
+impl CountingReader {
+    pub fn new(inner: Box<dyn Read + Send>,
+               node: Option<String>,
+               sink: Arc<dyn MetricsSink>)
+               -> CountingReader {
+        CountingReader {
+            inner,
+            node,
+            bytes: 0,
+            sink,
+        }
+    }
+}
+
+impl Read for CountingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+}
+
+impl Drop for CountingReader {
+    fn drop(&mut self) {
+        self.sink.bytes_downloaded(self.node.as_deref(), self.bytes);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate hyper;
+
+    use super::*;
+    use self::hyper::Url;
+    use blob::BlobContainer;
+    use dbcluster::DBCluster;
+    use sql::QueryRunner;
+    use std::io::Cursor;
+    use testing::{MockBackend, MockResponse};
+    use NoParams;
+
+    fn observation(operation: Operation, node: &str, error_code: Option<&str>) -> Observation {
+        Observation {
+            operation,
+            node: Some(node.to_string()),
+            error_code: error_code.map(String::from),
+            elapsed: Duration::from_millis(3),
+            server_duration: None,
+        }
+    }
+
+    #[test]
+    fn in_memory() {
+        let metrics = InMemoryMetrics::new();
+        metrics.observe(&observation(Operation::Query, "http://a:4200/", None));
+        metrics.observe(&observation(Operation::Query, "http://b:4200/", Some("4041")));
+        metrics.observe(&observation(Operation::Upload, "http://a:4200/", Some("409")));
+        metrics.bytes_uploaded(None, 10);
+
+        assert_eq!(metrics.observations().len(), 3);
+        assert_eq!(metrics.count(Operation::Query), 2);
+        assert_eq!(metrics.count(Operation::Fetch), 0);
+        assert_eq!(metrics.by_node()["http://a:4200/"], 2);
+        assert_eq!(metrics.errors()["4041"], 1);
+        assert_eq!(metrics.errors()["409"], 1);
+        assert_eq!(metrics.uploaded(), 10);
+
+        metrics.clear();
+        assert!(metrics.observations().is_empty());
+        assert_eq!(metrics.uploaded(), 0);
+    }
+
+    #[test]
+    fn counting_reader() {
+        let metrics = Arc::new(InMemoryMetrics::new());
+        {
+            let mut reader = CountingReader::new(Box::new(Cursor::new(vec![1u8; 300])),
+                                                 None,
+                                                 metrics.clone());
+            let mut buf = [0u8; 100];
+            reader.read_exact(&mut buf).unwrap();
+            assert_eq!(metrics.downloaded(), 0);
+        }
+        assert_eq!(metrics.downloaded(), 100);
+    }
+
+    #[test]
+    fn cluster() {
+        let backend = MockBackend::new();
+        backend.on("select 1", MockResponse::rows(vec!["1"], vec![vec![json!(1)]]).with_duration(2.0));
+        backend.on("select x", MockResponse::error(4043, "Column x unknown"));
+        let mut c = DBCluster::with_custom_backend(vec![Url::parse("http://db:4200").unwrap()],
+                                                   backend);
+        let metrics = Arc::new(InMemoryMetrics::new());
+        c.set_metrics_sink(metrics.clone());
+
+        c.query("select 1", None::<Box<NoParams>>).unwrap();
+        assert!(c.query("select x", None::<Box<NoParams>>).is_err());
+        let blob = c.put("b", &mut Cursor::new(vec![7u8; 1000])).unwrap();
+        let mut content = vec![];
+        c.get(&blob).unwrap().read_to_end(&mut content).unwrap();
+        c.delete(blob).unwrap();
+
+        let observations = metrics.observations();
+        assert_eq!(observations.len(), 5);
+        assert_eq!(observations[0].node, Some("http://db:4200/".to_string()));
+        assert_eq!(observations[0].server_duration, Some(2.0));
+        assert_eq!(metrics.count(Operation::Query), 2);
+        assert_eq!(metrics.count(Operation::Upload), 1);
+        assert_eq!(metrics.count(Operation::Fetch), 1);
+        assert_eq!(metrics.count(Operation::Delete), 1);
+        assert_eq!(metrics.errors()["4043"], 1);
+        assert_eq!(metrics.by_node()["http://db:4200/"], 5);
+        assert_eq!(metrics.uploaded(), 1000);
+        assert_eq!(metrics.downloaded(), 1000);
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate hyper;
+extern crate hyper_rustls;
+
+use self::hyper::net::{HttpConnector, NetworkConnector, SslClient};
+use self::hyper::header::{Headers, Authorization, Basic};
+use self::hyper_rustls::{TlsClient, WrappedStream};
+use std::io::{self, Read, Write};
+use config::ProxyConfig;
+
+// Upper bound for the proxy's answer to a CONNECT request
+const MAX_CONNECT_RESPONSE: usize = 8 * 1024;
+
+///
+/// Value of the `Proxy-Authorization` header, if the proxy requires credentials.
+///
+pub fn authorization(proxy: &ProxyConfig) -> Option<String> {
+    proxy
+        .user
+        .as_ref()
+        .and_then(|user| {
+            // the same format as an Authorization header, so let hyper encode it
+            let mut headers = Headers::new();
+            headers.set(Authorization(Basic {
+                                          username: user.clone(),
+                                          password: proxy.password.clone(),
+                                      }));
+            headers
+                .get_raw("Authorization")
+                .and_then(|raw| raw.first())
+                .map(|value| String::from_utf8_lossy(value).into_owned())
+        })
+}
+
+///
+/// Connects to `https` nodes through a tunnel that is opened with a `CONNECT`
+/// request to the proxy, authenticating if credentials are configured.
+///
+pub struct TunnelConnector {
+    proxy: ProxyConfig,
+    tls: TlsClient,
+}
+
+impl TunnelConnector {
+    pub fn new(proxy: ProxyConfig, tls: TlsClient) -> TunnelConnector {
+        TunnelConnector { proxy, tls }
+    }
+}
+
+impl NetworkConnector for TunnelConnector {
+    type Stream = WrappedStream;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> hyper::Result<WrappedStream> {
+        if scheme != "https" {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Cannot tunnel scheme '{}'", scheme))
+                               .into());
+        }
+        let mut stream = HttpConnector.connect(&self.proxy.host, self.proxy.port, "http")?;
+        write!(stream,
+               "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n",
+               host = host,
+               port = port)?;
+        if let Some(credentials) = authorization(&self.proxy) {
+            write!(stream, "Proxy-Authorization: {}\r\n", credentials)?;
+        }
+        write!(stream, "\r\n")?;
+        stream.flush()?;
+
+        let status = read_connect_status(&mut stream)?;
+        if (200..300).contains(&status) {
+            self.tls.wrap_client(stream, host)
+        } else {
+            Err(io::Error::new(io::ErrorKind::ConnectionRefused,
+                               format!("Proxy refused to tunnel to {}:{} with status {}",
+                                       host,
+                                       port,
+                                       status))
+                        .into())
+        }
+    }
+}
+
+// Reads the proxy's response head byte by byte (so nothing of the tunnelled
+// stream is consumed) and returns its status code.
+fn read_connect_status<R: Read>(stream: &mut R) -> io::Result<u16> {
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_CONNECT_RESPONSE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "Proxy response to CONNECT is too large"));
+        }
+        if stream.read(&mut byte)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "Proxy closed the connection during CONNECT"));
+        }
+        head.push(byte[0]);
+    }
+    String::from_utf8_lossy(&head)
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid proxy response to CONNECT"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{authorization, read_connect_status};
+    use config::ProxyConfig;
+    use std::io::Cursor;
+
+    #[test]
+    fn proxy_authorization() {
+        let mut proxy = ProxyConfig::new("proxy", 3128);
+        assert_eq!(authorization(&proxy), None);
+
+        proxy.user = Some("user".to_string());
+        proxy.password = Some("secret".to_string());
+        assert_eq!(authorization(&proxy),
+                   Some("Basic dXNlcjpzZWNyZXQ=".to_string()));
+    }
+
+    #[test]
+    fn connect_status() {
+        let mut response = Cursor::new(b"HTTP/1.1 200 Connection established\r\n\r\n\x16\x03".to_vec());
+        assert_eq!(read_connect_status(&mut response).unwrap(), 200);
+        assert_eq!(response.position(), 39);
+
+        let mut response = Cursor::new(b"HTTP/1.1 407 Proxy Authentication Required\r\n\
+                                         Content-Length: 0\r\n\r\n"
+                                               .to_vec());
+        assert_eq!(read_connect_status(&mut response).unwrap(), 407);
+
+        assert!(read_connect_status(&mut Cursor::new(b"HTTP/1.1 200 OK\r\n".to_vec())).is_err());
+        assert!(read_connect_status(&mut Cursor::new(b"garbage\r\n\r\n".to_vec())).is_err());
+    }
+}
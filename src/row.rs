@@ -16,10 +16,14 @@
 extern crate serde_json;
 extern crate serde;
 
-use serde_json::Value;
+use serde_json::{Map, Value};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use self::serde::de::DeserializeOwned;
-use std::rc::Rc;
+use std::sync::Arc;
+use convert::{from_column, mismatch, FromValue, Json};
+use error::ConversionError;
+use columns::Columns;
 
 ///
 /// A row in a result set of a CrateDB query. Provides
@@ -27,7 +31,7 @@ use std::rc::Rc;
 ///
 pub struct Row {
     wrapped: Vec<Value>,
-    columns: Rc<HashMap<String, usize>>,
+    columns: Arc<Columns>,
 }
 
 ///
@@ -54,99 +58,416 @@ pub trait ByColumnName {
     fn as_array<T: DeserializeOwned>(&self, col: &str) -> Option<Vec<T>>;
 }
 
+///
+/// A column of a row, either its index or its name.
+///
+pub trait ColumnIndex {
+    ///
+    /// The column's position in the row.
+    ///
+    fn position(&self, row: &Row) -> Result<usize, ConversionError>;
+
+    ///
+    /// The column's position in a result with these columns.
+    ///
+    fn column_position(&self, columns: &Columns) -> Result<usize, ConversionError>;
+
+    ///
+    /// The column's position in `len` values with these columns, as borrowed
+    /// by a `RowRef`.
+    ///
+    fn value_position(&self, columns: &Columns, len: usize) -> Result<usize, ConversionError> {
+        let idx = self.column_position(columns)?;
+        if idx < len {
+            Ok(idx)
+        } else {
+            Err(ConversionError::IndexOutOfRange { index: idx, len })
+        }
+    }
+}
+
+impl ColumnIndex for usize {
+    fn position(&self, row: &Row) -> Result<usize, ConversionError> {
+        if *self < row.wrapped.len() {
+            Ok(*self)
+        } else {
+            Err(ConversionError::IndexOutOfRange {
+                    index: *self,
+                    len: row.wrapped.len(),
+                })
+        }
+    }
+
+    fn column_position(&self, columns: &Columns) -> Result<usize, ConversionError> {
+        if *self < columns.len() {
+            Ok(*self)
+        } else {
+            Err(ConversionError::IndexOutOfRange {
+                    index: *self,
+                    len: columns.len(),
+                })
+        }
+    }
+
+    fn value_position(&self, _columns: &Columns, len: usize) -> Result<usize, ConversionError> {
+        if *self < len {
+            Ok(*self)
+        } else {
+            Err(ConversionError::IndexOutOfRange { index: *self, len })
+        }
+    }
+}
+
+impl ColumnIndex for &str {
+    fn position(&self, row: &Row) -> Result<usize, ConversionError> {
+        let idx = self.column_position(&row.columns)?;
+        idx.position(row)
+    }
+
+    fn column_position(&self, columns: &Columns) -> Result<usize, ConversionError> {
+        columns
+            .position(self)
+            .ok_or_else(|| ConversionError::NoSuchColumn { column: self.to_string() })
+    }
+}
+
+impl ColumnIndex for String {
+    fn position(&self, row: &Row) -> Result<usize, ConversionError> {
+        self.as_str().position(row)
+    }
+
+    fn column_position(&self, columns: &Columns) -> Result<usize, ConversionError> {
+        self.as_str().column_position(columns)
+    }
+}
+
 impl Row {
-    pub fn new(wrapped: Vec<Value>, headers: Rc<HashMap<String, usize>>) -> Row {
-        Row {
-            wrapped: wrapped,
-            columns: headers,
+    ///
+    /// Creates a row from column positions. Every call builds new `Columns`
+    /// (copying `headers` if it's shared); rows of one result should share
+    /// their columns through [`with_columns`](#method.with_columns).
+    ///
+    pub fn new(wrapped: Vec<Value>, headers: Arc<HashMap<String, usize>>) -> Row {
+        let headers = Arc::try_unwrap(headers).unwrap_or_else(|headers| (*headers).clone());
+        Row::with_columns(wrapped, Arc::new(Columns::from_positions(headers)))
+    }
+
+    ///
+    /// Creates a row of a result set with these columns.
+    ///
+    pub fn with_columns(wrapped: Vec<Value>, columns: Arc<Columns>) -> Row {
+        Row { wrapped, columns }
+    }
+
+    ///
+    /// The row's columns: their names in order and, if requested, types.
+    ///
+    pub fn columns(&self) -> &Columns {
+        &self.columns
+    }
+
+    ///
+    /// The number of values in the row.
+    ///
+    pub fn len(&self) -> usize {
+        self.wrapped.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.wrapped.is_empty()
+    }
+
+    ///
+    /// The row's values, in column order.
+    ///
+    pub fn values(&self) -> &[Value] {
+        &self.wrapped
+    }
+
+    ///
+    /// Moves the row's values out of it.
+    ///
+    pub fn into_values(self) -> Vec<Value> {
+        self.wrapped
+    }
+
+    ///
+    /// A borrowed view of this row.
+    ///
+    pub fn as_ref(&self) -> RowRef<'_> {
+        RowRef::new(&self.wrapped, &self.columns)
+    }
+
+    ///
+    /// Iterates over `(column name, value)` pairs, in column order. Values
+    /// without a known column name are named after their index.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// for (name, value) in row.iter() {
+    ///     println!("{}: {}", name, value);
+    /// }
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = (Cow<'_, str>, &Value)> {
+        self.wrapped.iter().enumerate().map(move |(i, value)| {
+            let name = match self.columns.name(i) {
+                Some(name) => Cow::Borrowed(name),
+                None => Cow::Owned(i.to_string()),
+            };
+            (name, value)
+        })
+    }
+
+    ///
+    /// Converts a column's value (by index or name) into any type that
+    /// implements [`FromValue`](../convert/trait.FromValue.html).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let id: i64 = row.get(0)?;
+    /// let name: Option<String> = row.get("name")?;
+    /// let tags = row.get::<Vec<String>, _>("tags")?;
+    /// ```
+    ///
+    /// # Errors
+    /// A `ConversionError` that tells a NULL value (use `Option<T>` for nullable
+    /// columns) from a value of another type, an unknown column name and an
+    /// index beyond the row's length, naming the column where possible.
+    ///
+    pub fn get<T: FromValue, C: ColumnIndex>(&self, column: C) -> Result<T, ConversionError> {
+        let idx = column.position(self)?;
+        from_column(&self.wrapped[idx], self.columns.column_type(idx))
+            .map_err(|e| match self.column_name(idx) {
+                         Some(name) => e.in_column(name),
+                         None => e.in_column(idx.to_string()),
+                     })
+    }
+
+    ///
+    /// An `OBJECT` column's value, by index or name; `None` if the column
+    /// doesn't exist or isn't an object.
+    ///
+    pub fn as_object<C: ColumnIndex>(&self, column: C) -> Option<Map<String, Value>> {
+        let idx = column.position(self).ok()?;
+        self.wrapped[idx].as_object().cloned()
+    }
+
+    ///
+    /// Deserializes an `OBJECT` column (by index or name) into a struct, or
+    /// anything else that implements `Deserialize`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// #[derive(Deserialize)]
+    /// struct User { id: i64, name: String }
+    ///
+    /// let user: User = row.object("payload")?;
+    /// ```
+    pub fn object<T: DeserializeOwned, C: ColumnIndex>(&self, column: C) -> Result<T, ConversionError> {
+        self.get::<Json<T>, C>(column).map(|json| json.0)
+    }
+
+    ///
+    /// Converts a value nested in an `OBJECT` column, like CrateDB's
+    /// `payload['user']['id']`: missing keys are NULL, and keys applied to an
+    /// array of objects return the array of their values.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let id: i64 = row.path("payload", &["user", "id"])?;
+    /// let email: Option<String> = row.path("payload", &["user", "email"])?;
+    /// ```
+    pub fn path<T: FromValue, C: ColumnIndex>(&self,
+                                              column: C,
+                                              path: &[&str])
+                                              -> Result<T, ConversionError> {
+        let idx = column.position(self)?;
+        let name = || {
+            let column = self.column_name(idx).map(String::from).unwrap_or_else(|| idx.to_string());
+            path.iter().fold(column, |name, key| format!("{}['{}']", name, key))
+        };
+        let mut value = Cow::Borrowed(&self.wrapped[idx]);
+        for key in path {
+            value = match value {
+                Cow::Borrowed(v) => subscript(v, key),
+                Cow::Owned(ref v) => subscript(v, key).map(|v| Cow::Owned(v.into_owned())),
+            }.map_err(|e| e.in_column(name()))?;
+        }
+        T::from_value(&value).map_err(|e| e.in_column(name()))
+    }
+
+    ///
+    /// The name of the column at this index, if known.
+    ///
+    pub fn column_name(&self, idx: usize) -> Option<&str> {
+        self.columns.name(idx)
+    }
+}
+
+static NULL: Value = Value::Null;
+
+///
+/// A row borrowed from a result set, e.g. by
+/// [`RowIterator::iter`](../struct.RowIterator.html#method.iter), to read it
+/// without copying its values.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct RowRef<'a> {
+    values: &'a [Value],
+    columns: &'a Columns,
+}
+
+impl<'a> RowRef<'a> {
+    pub fn new(values: &'a [Value], columns: &'a Columns) -> RowRef<'a> {
+        RowRef { values, columns }
+    }
+
+    pub fn columns(&self) -> &'a Columns {
+        self.columns
+    }
+
+    pub fn values(&self) -> &'a [Value] {
+        self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    ///
+    /// A column's value, by index or name.
+    ///
+    pub fn value<C: ColumnIndex>(&self, column: C) -> Result<&'a Value, ConversionError> {
+        let idx = column.value_position(self.columns, self.values.len())?;
+        Ok(&self.values[idx])
+    }
+
+    ///
+    /// Converts a column's value, like [`Row::get`](struct.Row.html#method.get).
+    ///
+    pub fn get<T: FromValue, C: ColumnIndex>(&self, column: C) -> Result<T, ConversionError> {
+        let idx = column.value_position(self.columns, self.values.len())?;
+        from_column(&self.values[idx], self.columns.column_type(idx))
+            .map_err(|e| match self.columns.name(idx) {
+                         Some(name) => e.in_column(name),
+                         None => e.in_column(idx.to_string()),
+                     })
+    }
+
+    ///
+    /// Copies the row into an owned `Row`.
+    ///
+    pub fn to_row(&self) -> Row {
+        Row::with_columns(self.values.to_vec(), Arc::new(self.columns.clone()))
+    }
+}
+
+// The value of `value[key]`.
+fn subscript<'a>(value: &'a Value, key: &str) -> Result<Cow<'a, Value>, ConversionError> {
+    match *value {
+        Value::Object(ref map) => Ok(Cow::Borrowed(map.get(key).unwrap_or(&NULL))),
+        Value::Null => Ok(Cow::Borrowed(&NULL)),
+        Value::Array(ref values) => {
+            values.iter()
+                .map(|v| subscript(v, key).map(Cow::into_owned))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|values| Cow::Owned(Value::Array(values)))
         }
+        _ => Err(mismatch("object", value)),
     }
 }
 
 impl ByIndex for Row {
     fn as_string(&self, idx: usize) -> Option<String> {
-        match self.wrapped[idx].as_str() {
-            Some(r) => Some(r.to_string()),
-            _ => None,
-        }
+        self.wrapped.get(idx).and_then(|v| v.as_str()).map(|r| r.to_string())
     }
 
     fn as_i64(&self, idx: usize) -> Option<i64> {
-        self.wrapped[idx].as_i64()
+        self.wrapped.get(idx).and_then(|v| v.as_i64())
     }
 
     fn as_u64(&self, idx: usize) -> Option<u64> {
-        self.wrapped[idx].as_u64()
+        self.wrapped.get(idx).and_then(|v| v.as_u64())
     }
 
     fn as_f64(&self, idx: usize) -> Option<f64> {
-        self.wrapped[idx].as_f64()
+        self.wrapped.get(idx).and_then(|v| v.as_f64())
     }
 
     fn as_bool(&self, idx: usize) -> Option<bool> {
-        self.wrapped[idx].as_bool()
+        self.wrapped.get(idx).and_then(|v| v.as_bool())
     }
 
     fn as_array<T: DeserializeOwned>(&self, idx: usize) -> Option<Vec<T>> {
-        match self.wrapped[idx].as_array() {
-            Some(v) => {
-                Some(v.into_iter().map(|e| serde_json::from_value(e.clone()).unwrap()).collect())
-            }
-            None => None,
-        }
-
+        self.wrapped
+            .get(idx)
+            .and_then(|v| v.as_array())
+            .and_then(|v| v.iter().map(|e| T::deserialize(e).ok()).collect())
     }
 }
 
 impl ByColumnName for Row {
     fn as_string(&self, col: &str) -> Option<String> {
-        match self.columns.get(col) {
-            Some(idx) => ByIndex::as_string(self, *idx),
+        match self.columns.position(col) {
+            Some(idx) => ByIndex::as_string(self, idx),
             None => None,
         }
     }
 
     fn as_i64(&self, col: &str) -> Option<i64> {
-        match self.columns.get(col) {
-            Some(idx) => ByIndex::as_i64(self, *idx),
+        match self.columns.position(col) {
+            Some(idx) => ByIndex::as_i64(self, idx),
             None => None,
         }
     }
 
     fn as_u64(&self, col: &str) -> Option<u64> {
-        match self.columns.get(col) {
-            Some(idx) => ByIndex::as_u64(self, *idx),
+        match self.columns.position(col) {
+            Some(idx) => ByIndex::as_u64(self, idx),
             None => None,
         }
     }
 
     fn as_f64(&self, col: &str) -> Option<f64> {
-        match self.columns.get(col) {
-            Some(idx) => ByIndex::as_f64(self, *idx),
+        match self.columns.position(col) {
+            Some(idx) => ByIndex::as_f64(self, idx),
             None => None,
         }
     }
 
     fn as_bool(&self, col: &str) -> Option<bool> {
-        match self.columns.get(col) {
-            Some(idx) => ByIndex::as_bool(self, *idx),
+        match self.columns.position(col) {
+            Some(idx) => ByIndex::as_bool(self, idx),
             None => None,
         }
     }
 
     fn as_array<T: DeserializeOwned>(&self, col: &str) -> Option<Vec<T>> {
-        match self.columns.get(col) {
-            Some(idx) => ByIndex::as_array(self, *idx),
+        match self.columns.position(col) {
+            Some(idx) => ByIndex::as_array(self, idx),
             None => None,
         }
     }
 }
 #[cfg(test)]
+#[allow(clippy::needless_return, clippy::unnecessary_to_owned)]
 mod tests {
     extern crate serde_json;
-    use super::{Row, ByColumnName, ByIndex};
+    use super::{Row, RowRef, ByColumnName, ByIndex};
+    use convert::Json;
+    use columns::{Columns, ColumnType};
+    use error::ConversionError;
+    use std::collections::BTreeMap;
     use std::collections::HashMap;
-    use std::rc::Rc;
+    use std::sync::Arc;
 
     fn get_row() -> Row {
         let mut v_obj = HashMap::new();
@@ -170,7 +491,7 @@ mod tests {
                      serde_json::to_value(vec![1, 2, 3, 4]).unwrap(),
                      serde_json::to_value(vec![vec![1, 1], vec![2, 2]]).unwrap()];
 
-        return Row::new(v, Rc::new(headers));
+        return Row::new(v, Arc::new(headers));
     }
 
     #[test]
@@ -204,4 +525,200 @@ mod tests {
         assert_eq!(ByIndex::as_array(&row, 6),
                    Some(vec![vec![1, 1], vec![2, 2]]));
     }
+
+    #[test]
+    fn get() {
+        let row = get_row();
+        assert_eq!(row.get::<String, _>(0), Ok("hello".to_string()));
+        assert_eq!(row.get::<u64, _>("uint"), Ok(1234));
+        assert_eq!(row.get::<i32, _>("sint"), Ok(-1234));
+        assert_eq!(row.get::<f64, _>("float"), Ok(3.141528));
+        assert_eq!(row.get::<bool, _>("bool".to_string()), Ok(true));
+        assert_eq!(row.get::<Option<i64>, _>("uint"), Ok(Some(1234)));
+        assert_eq!(row.get::<Vec<u8>, _>("array"), Ok(vec![1, 2, 3, 4]));
+        assert_eq!(row.get::<Json<Vec<(i32, i32)>>, _>("array_of_arrays"),
+                   Ok(Json(vec![(1, 1), (2, 2)])));
+        assert_eq!(row.get::<serde_json::Value, _>(5), Ok(json!([1, 2, 3, 4])));
+        assert!(row.get::<BTreeMap<String, i64>, _>("array").is_err());
+    }
+
+    #[test]
+    fn get_errors() {
+        let row = get_row();
+        assert_eq!(row.get::<i64, _>("missing"),
+                   Err(ConversionError::NoSuchColumn { column: "missing".to_string() }));
+        assert_eq!(row.get::<i64, _>("missing").unwrap_err().to_string(),
+                   "No such column 'missing'");
+        assert_eq!(row.get::<i64, _>(7),
+                   Err(ConversionError::IndexOutOfRange { index: 7, len: 7 }));
+        assert_eq!(row.get::<i64, _>(7).unwrap_err().to_string(),
+                   "Index 7 out of range for a row of 7 columns");
+        assert_eq!(row.get::<i64, _>(0),
+                   Err(ConversionError::TypeMismatch {
+                           column: Some("str".to_string()),
+                           expected: "i64".to_string(),
+                           actual: "string".to_string(),
+                       }));
+        assert_eq!(row.get::<i64, _>("str").unwrap_err().to_string(),
+                   "Column 'str': expected i64, got string");
+        assert_eq!(row.get::<Vec<String>, _>("array").unwrap_err().to_string(),
+                   "Column 'array': expected String, got integer");
+    }
+
+    #[test]
+    fn nulls() {
+        let mut headers = HashMap::new();
+        headers.insert("name".to_owned(), 0usize);
+        let row = Row::new(vec![serde_json::Value::Null], Arc::new(headers));
+        assert_eq!(row.get::<String, _>("name"),
+                   Err(ConversionError::Null { column: Some("name".to_string()) }));
+        assert_eq!(row.get::<String, _>(0).unwrap_err().to_string(),
+                   "Column 'name' is NULL");
+        assert_eq!(row.get::<Option<String>, _>(0), Ok(None));
+        assert_eq!(row.column_name(0), Some("name"));
+        assert_eq!(row.column_name(1), None);
+    }
+
+    #[test]
+    fn out_of_range() {
+        let row = get_row();
+        assert_eq!(ByIndex::as_i64(&row, 100), None);
+        assert_eq!(ByIndex::as_string(&row, 100), None);
+        assert_eq!(ByIndex::as_array::<i64>(&row, 100), None);
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct User {
+        id: i64,
+        name: String,
+    }
+
+    fn object_row() -> Row {
+        let mut headers = HashMap::new();
+        headers.insert("payload".to_owned(), 0usize);
+        headers.insert("tags".to_owned(), 1usize);
+        Row::new(vec![json!({"user": {"id": 1, "name": "alice"}, "items": [{"sku": "a"}, {"sku": "b"}]}),
+                      json!("none")],
+                 Arc::new(headers))
+    }
+
+    #[test]
+    fn objects() {
+        let row = object_row();
+        let payload = row.as_object("payload").unwrap();
+        assert_eq!(payload["user"]["name"], "alice");
+        assert_eq!(row.as_object(0), Some(payload));
+        assert_eq!(row.as_object(1), None);
+        assert_eq!(row.as_object(2), None);
+        assert_eq!(row.as_object("missing"), None);
+
+        assert_eq!(row.object::<User, _>("payload").ok(), None);
+        let user: User = row.path::<Json<User>, _>("payload", &["user"]).unwrap().0;
+        assert_eq!(user, User { id: 1, name: "alice".to_string() });
+        let mut user_row = object_row();
+        user_row.wrapped[0] = json!({"id": 2, "name": "bob"});
+        assert_eq!(user_row.object::<User, _>(0), Ok(User { id: 2, name: "bob".to_string() }));
+    }
+
+    #[test]
+    fn paths() {
+        let row = object_row();
+        assert_eq!(row.path::<i64, _>("payload", &["user", "id"]), Ok(1));
+        assert_eq!(row.path::<String, _>(0, &["user", "name"]), Ok("alice".to_string()));
+        assert_eq!(row.path::<Option<String>, _>("payload", &["user", "email"]), Ok(None));
+        assert_eq!(row.path::<Option<String>, _>("payload", &["nobody", "email"]), Ok(None));
+        assert_eq!(row.path::<Vec<String>, _>("payload", &["items", "sku"]),
+                   Ok(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(row.path::<serde_json::Value, _>("payload", &["user"]),
+                   Ok(json!({"id": 1, "name": "alice"})));
+
+        assert_eq!(row.path::<String, _>("payload", &["user", "email"]).unwrap_err().to_string(),
+                   "Column 'payload['user']['email']' is NULL");
+        assert_eq!(row.path::<i64, _>("payload", &["user", "name"]).unwrap_err().to_string(),
+                   "Column 'payload['user']['name']': expected i64, got string");
+        assert_eq!(row.path::<i64, _>("tags", &["a"]).unwrap_err().to_string(),
+                   "Column 'tags['a']': expected object, got string");
+        assert_eq!(row.path::<i64, _>("missing", &["a"]),
+                   Err(ConversionError::NoSuchColumn { column: "missing".to_string() }));
+    }
+
+    #[test]
+    fn metadata() {
+        let row = get_row();
+        assert_eq!(row.len(), 7);
+        assert!(!row.is_empty());
+        assert_eq!(row.columns().names()[..3], ["str".to_string(), "uint".to_string(), "float".to_string()]);
+        assert_eq!(row.columns().types(), None);
+        assert_eq!(row.values()[1], json!(1234));
+
+        let pairs: Vec<(String, serde_json::Value)> = row.iter()
+            .take(2)
+            .map(|(name, value)| (name.into_owned(), value.clone()))
+            .collect();
+        assert_eq!(pairs,
+                   vec![("str".to_string(), json!("hello")), ("uint".to_string(), json!(1234))]);
+
+        let columns = Columns::new(vec!["a".to_string()], Some(vec![ColumnType::Long]));
+        let row = Row::with_columns(vec![json!(1), json!(2)], Arc::new(columns));
+        let names: Vec<String> = row.iter().map(|(name, _)| name.into_owned()).collect();
+        assert_eq!(names, vec!["a", "1"]);
+        assert_eq!(row.columns().column_type(0), Some(&ColumnType::Long));
+    }
+
+    #[test]
+    fn sparse_headers() {
+        let mut headers = HashMap::new();
+        headers.insert("id".to_string(), 0);
+        headers.insert("temp".to_string(), 2);
+        let row = Row::new(vec![json!(1), json!("x"), json!(3.5)], Arc::new(headers));
+        assert_eq!(row.get::<f64, _>("temp"), Ok(3.5));
+        assert_eq!(row.as_ref().get::<f64, _>("temp"), Ok(3.5));
+        let pairs: Vec<(String, serde_json::Value)> = row.iter()
+            .map(|(name, value)| (name.into_owned(), value.clone()))
+            .collect();
+        assert_eq!(pairs,
+                   vec![("id".to_string(), json!(1)),
+                        ("1".to_string(), json!("x")),
+                        ("temp".to_string(), json!(3.5))]);
+    }
+
+    #[test]
+    fn short_and_malformed() {
+        let columns = Arc::new(Columns::new(vec!["a".to_string(), "b".to_string()], None));
+        let row = Row::with_columns(vec![json!([1, "x"])], columns.clone());
+        assert_eq!(row.get::<i64, _>("b"),
+                   Err(ConversionError::IndexOutOfRange { index: 1, len: 1 }));
+        assert_eq!(ByColumnName::as_i64(&row, "b"), None);
+        assert_eq!(ByIndex::as_array::<i64>(&row, 0), None);
+        assert_eq!(row.into_values(), vec![json!([1, "x"])]);
+    }
+
+    #[test]
+    fn borrowed() {
+        let columns = Columns::new(vec!["a".to_string(), "b".to_string()], None);
+        let values = vec![json!(1), json!(null)];
+        let row = RowRef::new(&values, &columns);
+        assert_eq!(row.get::<i64, _>("a"), Ok(1));
+        assert_eq!(row.get::<Option<String>, _>(1), Ok(None));
+        assert_eq!(row.get::<String, _>("b"),
+                   Err(ConversionError::Null { column: Some("b".to_string()) }));
+        assert_eq!(row.value("a"), Ok(&json!(1)));
+        assert_eq!(row.value(2), Err(ConversionError::IndexOutOfRange { index: 2, len: 2 }));
+        assert_eq!(RowRef::new(&values[..1], &columns).value("b"),
+                   Err(ConversionError::IndexOutOfRange { index: 1, len: 1 }));
+
+        let values = vec![json!(1), json!(null), json!("extra")];
+        let row = RowRef::new(&values, &columns);
+        assert_eq!(row.value(2), Ok(&json!("extra")));
+        assert_eq!(row.get::<i64, _>(2),
+                   Err(ConversionError::TypeMismatch {
+                           column: Some("2".to_string()),
+                           expected: "i64".to_string(),
+                           actual: "string".to_string(),
+                       }));
+        let owned = row.to_row();
+        assert_eq!(owned.get::<i64, _>(2), row.get::<i64, _>(2));
+        assert_eq!(owned.values(), &values[..]);
+        assert_eq!(owned.as_ref().get::<i64, _>("a"), Ok(1));
+    }
 }
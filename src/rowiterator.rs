@@ -14,40 +14,304 @@
 
 
 extern crate serde_json;
+#[cfg(feature = "arrow")]
+extern crate arrow_array;
+#[cfg(feature = "arrow")]
+extern crate arrow_schema;
 
-use row::Row;
-use std::collections::HashMap;
+use row::{ColumnIndex, Row, RowRef};
+use convert::{from_column, mismatch, FromValue};
+use error::ConversionError;
+#[cfg(feature = "arrow")]
+use arrow::{self, RecordBatches};
+#[cfg(feature = "arrow")]
+use self::arrow_array::RecordBatch;
+#[cfg(feature = "arrow")]
+use self::arrow_schema::ArrowError;
+use columns::Columns;
+use export::{self, CsvOptions};
 use self::serde_json::Value;
-use std::rc::Rc;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::vec;
 
+///
+/// The rows of a query result. Iterating moves each row's values out of the
+/// result; [`iter`](#method.iter) borrows them instead.
+///
 #[derive(Debug)]
 pub struct RowIterator {
-    rows: Vec<Value>,
-    header: Rc<HashMap<String, usize>>,
+    rows: vec::IntoIter<Value>,
+    columns: Arc<Columns>,
+}
+
+// A row's values. CrateDB returns rows as arrays (`query` rejects anything
+// else); other values are read as rows without values rather than panicking.
+fn values(row: &Value) -> &[Value] {
+    match *row {
+        Value::Array(ref values) => values,
+        _ => &[],
+    }
 }
 
 impl RowIterator {
-    pub fn new(mut rows: Vec<Value>, header: HashMap<String, usize>) -> RowIterator {
-        let headers = Rc::new(header);
-        rows.reverse();
+    pub fn new(rows: Vec<Value>, header: HashMap<String, usize>) -> RowIterator {
+        RowIterator::with_columns(rows, Columns::from_positions(header))
+    }
+
+    ///
+    /// Creates the rows of a result set with these columns.
+    ///
+    pub fn with_columns(rows: Vec<Value>, columns: Columns) -> RowIterator {
         RowIterator {
-            rows: rows,
-            header: headers,
+            rows: rows.into_iter(),
+            columns: Arc::new(columns),
         }
     }
 
+    ///
+    /// The number of remaining rows.
+    ///
     pub fn len(&self) -> usize {
         self.rows.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.len() == 0
+    }
+
+    ///
+    /// Borrows the remaining rows, without copying or consuming them.
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = RowRef<'_>> {
+        let columns = &*self.columns;
+        self.rows.as_slice().iter().map(move |row| RowRef::new(values(row), columns))
+    }
+
+    ///
+    /// The result's columns: their names in order and, if requested, types.
+    ///
+    pub fn columns(&self) -> &Columns {
+        &self.columns
+    }
+
+    ///
+    /// The remaining rows' values of a column, by name or index, in row order.
+    /// Borrows them from the result instead of copying rows; values missing
+    /// from a row are NULL, rows that aren't arrays are a type mismatch.
+    ///
+    pub fn column_values<C: ColumnIndex>(&self, column: C) -> Result<Vec<&Value>, ConversionError> {
+        static NULL: Value = Value::Null;
+        let idx = column.column_position(&self.columns)?;
+        self.rows
+            .as_slice()
+            .iter()
+            .map(|row| match *row {
+                     Value::Array(ref values) => Ok(values.get(idx).unwrap_or(&NULL)),
+                     _ => Err(mismatch("array", row).in_column(self.column_name(idx))),
+                 })
+            .collect()
+    }
+
+    ///
+    /// The remaining rows' values of a column, by name or index, converted to
+    /// `T`. Use `Option<T>` for columns with NULL values.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let (_, rows) = c.query("select city, avg(temp) as avg_temp from sensors group by city",
+    ///                         None::<Box<NoParams>>)?;
+    /// let cities: Vec<String> = rows.column("city")?;
+    /// let temps: Vec<Option<f64>> = rows.column(1)?;
+    /// ```
+    pub fn column<T: FromValue, C: ColumnIndex>(&self, column: C) -> Result<Vec<T>, ConversionError> {
+        let idx = column.column_position(&self.columns)?;
+        let column_type = self.columns.column_type(idx);
+        self.column_values(idx)?
+            .into_iter()
+            .map(|value| {
+                     from_column(value, column_type).map_err(|e| e.in_column(self.column_name(idx)))
+                 })
+            .collect()
+    }
+
+    // The name of a column in errors, or its index if it has none.
+    fn column_name(&self, idx: usize) -> String {
+        self.columns.name(idx).map_or_else(|| idx.to_string(), String::from)
+    }
+
+    ///
+    /// Writes the remaining rows as CSV and returns how many were written.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let (_, rows) = c.query("select * from sensors", None::<Box<NoParams>>)?;
+    /// rows.write_csv(File::create("sensors.csv")?, &CsvOptions::default())?;
+    /// ```
+    pub fn write_csv<W: Write>(self, out: W, options: &CsvOptions) -> io::Result<usize> {
+        let columns = self.columns.clone();
+        export::write_csv(&columns, self, out, options)
+    }
+
+    ///
+    /// Writes the remaining rows as JSON objects keyed by column name, one per
+    /// line, and returns how many were written.
+    ///
+    pub fn write_json_lines<W: Write>(self, out: W) -> io::Result<usize> {
+        export::write_json_lines(self, out)
+    }
+
+    ///
+    /// Writes the remaining rows as an aligned text table and returns how many
+    /// were written.
+    ///
+    pub fn write_table<W: Write>(self, out: W) -> io::Result<usize> {
+        let columns = self.columns.clone();
+        export::write_table(&columns, self, out)
+    }
+
+    ///
+    /// Converts the remaining rows into one Arrow record batch (`arrow`
+    /// feature).
+    ///
+    #[cfg(feature = "arrow")]
+    pub fn to_record_batch(self) -> Result<RecordBatch, ArrowError> {
+        let columns = self.columns.clone();
+        let rows: Vec<Row> = self.collect();
+        arrow::record_batch(Arc::new(arrow::schema(&columns, &rows)), &rows)
+    }
+
+    ///
+    /// Converts the remaining rows into Arrow record batches of up to
+    /// `chunk_size` rows (`arrow` feature).
+    ///
+    #[cfg(feature = "arrow")]
+    pub fn record_batches(self, chunk_size: usize) -> RecordBatches {
+        let columns = self.columns.clone();
+        RecordBatches::new(&columns, self.collect(), chunk_size)
+    }
 }
 
 impl Iterator for RowIterator {
     type Item = Row;
 
     fn next(&mut self) -> Option<Row> {
-        match self.rows.pop() {
-            Some(i) => Some(Row::new(i.as_array().unwrap().to_vec(), self.header.clone())),
-            _ => None,
-        }
+        let values = match self.rows.next()? {
+            Value::Array(values) => values,
+            _ => vec![],
+        };
+        Some(Row::with_columns(values, self.columns.clone()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> RowIterator {
+        RowIterator::with_columns(vec![json!(["berlin", 12.5]),
+                                       json!(["vienna", null]),
+                                       json!(["graz"]),
+                                       json!(["linz", 9])],
+                                  Columns::new(vec!["city".to_string(), "avg_temp".to_string()],
+                                               None))
+    }
+
+    #[test]
+    fn positions() {
+        let mut header = HashMap::new();
+        header.insert("avg_temp".to_string(), 1);
+        header.insert("city".to_string(), 0);
+        let mut rows = RowIterator::new(vec![json!(["berlin", 12.5]), json!(["graz", 9])], header);
+        assert_eq!(rows.columns().names(), &["city".to_string(), "avg_temp".to_string()][..]);
+        let first = rows.next().unwrap();
+        let second = rows.next().unwrap();
+        assert!(::std::ptr::eq(first.columns(), second.columns()));
+        assert_eq!(second.get::<f64, _>("avg_temp"), Ok(9.0));
+    }
+
+    #[test]
+    fn sparse_positions() {
+        let mut header = HashMap::new();
+        header.insert("id".to_string(), 0);
+        header.insert("temp".to_string(), 2);
+        let rows = RowIterator::new(vec![json!([1, "x", 3.5]), json!([2, "y", 4])], header);
+        assert_eq!(rows.column::<f64, _>("temp"), Ok(vec![3.5, 4.0]));
+        assert_eq!(rows.column::<i64, _>(1),
+                   Err(ConversionError::TypeMismatch {
+                           column: Some("1".to_string()),
+                           expected: "i64".to_string(),
+                           actual: "string".to_string(),
+                       }));
+    }
+
+    #[test]
+    fn columns() {
+        let mut rows = rows();
+        let cities: Vec<String> = rows.column("city").unwrap();
+        assert_eq!(cities, vec!["berlin", "vienna", "graz", "linz"]);
+        let temps: Vec<Option<f64>> = rows.column(1).unwrap();
+        assert_eq!(temps, vec![Some(12.5), None, None, Some(9.0)]);
+        assert_eq!(rows.column_values("avg_temp").unwrap()[0], &json!(12.5));
+
+        rows.next();
+        let temps: Vec<Option<f64>> = rows.column("avg_temp").unwrap();
+        assert_eq!(temps, vec![None, None, Some(9.0)]);
+        assert_eq!(rows.len(), 3);
+    }
+
+    #[test]
+    fn column_errors() {
+        let rows = rows();
+        assert_eq!(rows.column::<f64, _>("avg_temp"),
+                   Err(ConversionError::Null { column: Some("avg_temp".to_string()) }));
+        assert_eq!(rows.column::<i64, _>("city"),
+                   Err(ConversionError::TypeMismatch {
+                           column: Some("city".to_string()),
+                           expected: "i64".to_string(),
+                           actual: "string".to_string(),
+                       }));
+        assert_eq!(rows.column::<String, _>("country"),
+                   Err(ConversionError::NoSuchColumn { column: "country".to_string() }));
+        assert_eq!(rows.column_values(2),
+                   Err(ConversionError::IndexOutOfRange { index: 2, len: 2 }));
+    }
+
+    #[test]
+    fn borrowed_and_malformed() {
+        let mut rows = RowIterator::with_columns(vec![json!([1, "a"]), json!(2), json!(null), json!([])],
+                                                 Columns::new(vec!["id".to_string(),
+                                                                   "name".to_string()],
+                                                              None));
+        let ids: Vec<Result<Option<i64>, ConversionError>> =
+            rows.iter().map(|row| row.get("id")).collect();
+        let missing = ConversionError::IndexOutOfRange { index: 0, len: 0 };
+        assert_eq!(ids,
+                   vec![Ok(Some(1)), Err(missing.clone()), Err(missing.clone()), Err(missing)]);
+        assert_eq!(rows.iter().next().unwrap().get::<String, _>("name"), Ok("a".to_string()));
+        assert_eq!(rows.column_values("id"),
+                   Err(ConversionError::TypeMismatch {
+                           column: Some("id".to_string()),
+                           expected: "array".to_string(),
+                           actual: "integer".to_string(),
+                       }));
+        assert_eq!(rows.len(), 4);
+
+        let first = rows.next().unwrap();
+        assert_eq!(first.values(), &[json!(1), json!("a")][..]);
+        assert!(rows.next().unwrap().is_empty());
+        assert!(rows.next().unwrap().is_empty());
+        assert_eq!(rows.size_hint(), (1, Some(1)));
+        assert!(rows.next().unwrap().is_empty());
+        assert!(rows.next().is_none());
+        assert!(rows.is_empty());
     }
 }
@@ -16,16 +16,21 @@ extern crate serde;
 extern crate hyper;
 extern crate serde_json;
 extern crate rand;
+extern crate ring;
 
 use dbcluster::DBCluster;
+use self::ring::digest;
 use self::serde_json::Value;
 use self::serde::ser::Serialize;
 use error::CrateDBError;
 use rowiterator::RowIterator;
-use std::collections::HashMap;
+use columns::{Columns, ColumnType};
+use std::time::{Duration, Instant};
 use std::convert::Into;
 use backend::{Backend, BackendResult};
 use dbcluster::{Loadbalancing, EndpointType};
+use instrument;
+use interceptor::{Outcome, Statement};
 
 ///
 /// Empty struct to pass into argument lists for the Box to have a type.
@@ -33,15 +38,58 @@ use dbcluster::{Loadbalancing, EndpointType};
 #[derive(Serialize)]
 pub struct Nothing {}
 
+///
+/// A statement that took longer than the cluster's slow query threshold.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowQuery {
+    /// The statement as it was sent
+    pub statement: String,
+
+    /// A hash of the parameters, to tell runs of the same statement apart
+    /// without exposing their values. Stable across builds and releases (the
+    /// first 8 bytes of the SHA-256 of their JSON). `None` if there weren't any.
+    pub params_hash: Option<u64>,
+
+    /// The node the statement ran on
+    pub node: Option<String>,
+
+    /// The duration the server reported (in ms), `None` if the query failed
+    pub server_duration: Option<f64>,
+
+    /// The wall-clock duration of the round trip
+    pub round_trip: Duration,
+}
+
 
 trait Executor {
-    fn execute<SQL, S>(&self,
-                       sql: SQL,
-                       bulk: bool,
-                       params: Option<Box<S>>)
-                       -> (BackendResult, String)
+    fn execute<SQL, S, R, F>(&self,
+                             sql: SQL,
+                             bulk: bool,
+                             params: Option<Box<S>>,
+                             parse: F)
+                             -> Result<(f64, R), CrateDBError>
         where SQL: Into<String>,
-              S: Serialize;
+              S: Serialize,
+              R: Intercepted,
+              F: FnOnce(BackendResult, String) -> Result<(f64, R), CrateDBError>;
+}
+
+// Results that interceptors see after a statement.
+trait Intercepted {
+    fn outcome(&self) -> Outcome<'_>;
+}
+
+impl Intercepted for RowIterator {
+    fn outcome(&self) -> Outcome<'_> {
+        Outcome::Rows(self)
+    }
+}
+
+impl Intercepted for Vec<i64> {
+    fn outcome(&self) -> Outcome<'_> {
+        Outcome::RowCounts(self)
+    }
 }
 
 
@@ -92,41 +140,96 @@ pub trait QueryRunner {
 
 
 impl<T: Backend + Sized> Executor for DBCluster<T> {
-    // Executes the query against the backend.
-    fn execute<SQL, S>(&self,
-                       sql: SQL,
-                       bulk: bool,
-                       params: Option<Box<S>>)
-                       -> (BackendResult, String)
+    // Runs the query through the interceptors, against the backend.
+    fn execute<SQL, S, R, F>(&self,
+                             sql: SQL,
+                             bulk: bool,
+                             params: Option<Box<S>>,
+                             parse: F)
+                             -> Result<(f64, R), CrateDBError>
         where SQL: Into<String>,
-              S: Serialize
+              S: Serialize,
+              R: Intercepted,
+              F: FnOnce(BackendResult, String) -> Result<(f64, R), CrateDBError>
     {
-        let url = self.get_endpoint(EndpointType::SQL);
-        let json_query = if bulk {
-            json!({
-                "stmt": sql.into(),
-                "bulk_args": serde_json::to_value(params.unwrap()).unwrap()
-                })
-                    .to_string()
-        } else if let Some(p) = params {
-            json!({
-                    "stmt": sql.into(),
-                    "args": serde_json::to_value(p).unwrap()
-                    })
-                    .to_string()
-        } else {
-            json!({
-                    "stmt": sql.into()
-                    })
-                    .to_string()
+        let mut statement = Statement {
+            stmt: sql.into(),
+            params: params.map(|p| serde_json::to_value(p).unwrap()),
+            bulk,
         };
-        match self.backend.execute(url, json_query) {
+        let interceptors = self.interceptors();
+        let mut passed = 0;
+        let mut blocked = None;
+        for interceptor in interceptors {
+            if let Err(e) = interceptor.before(&mut statement) {
+                blocked = Some(e);
+                break;
+            }
+            passed += 1;
+        }
+
+        let parsed = match blocked {
+            Some(e) => Err(e),
+            None => self.send(&statement, parse),
+        };
+        for interceptor in interceptors[..passed].iter().rev() {
+            let result = parsed.as_ref().map(|&(duration, ref result)| (duration, result.outcome()));
+            interceptor.after(&statement, result);
+        }
+        parsed
+    }
+}
+
+impl<T: Backend + Sized> DBCluster<T> {
+    // Sends the statement to a node and parses the response.
+    fn send<R, F>(&self, statement: &Statement, parse: F) -> Result<(f64, R), CrateDBError>
+        where F: FnOnce(BackendResult, String) -> Result<(f64, R), CrateDBError>
+    {
+        let url = self.get_endpoint(EndpointType::SQL)
+            .map(|url| if self.column_types() { url + "?types" } else { url });
+        let operation = instrument::query(url.as_deref(),
+                                          &statement.stmt,
+                                          statement.params.as_ref(),
+                                          statement.bulk,
+                                          self.log_parameters());
+        let json_query = match statement.params {
+            Some(ref p) if statement.bulk => json!({ "stmt": statement.stmt, "bulk_args": p }),
+            Some(ref p) => json!({ "stmt": statement.stmt, "args": p }),
+            None => json!({ "stmt": statement.stmt }),
+        };
+        let node = url.as_deref().map(instrument::node);
+        let started = Instant::now();
+        let (result, body) = match self.backend.execute(url, json_query.to_string()) {
             Ok(r) => r,
             Err(e) => (BackendResult::Error, e.description),
+        };
+        let round_trip = started.elapsed();
+        let parsed = parse(result, body);
+        if let Some(&(threshold, ref callback)) = self.slow_queries() {
+            if round_trip >= threshold {
+                callback(&SlowQuery {
+                              statement: statement.stmt.clone(),
+                              params_hash: statement.params.as_ref().map(hash),
+                              node,
+                              server_duration: parsed.as_ref().ok().map(|&(duration, _)| duration),
+                              round_trip,
+                          });
+            }
         }
+        operation.finish(&parsed,
+                         parsed.as_ref().ok().map(|&(duration, _)| duration),
+                         &**self.metrics());
+        parsed
     }
 }
 
+// The first 8 bytes of the SHA-256 digest of the parameters' JSON
+// representation, which (unlike `DefaultHasher`) stays the same across builds.
+fn hash(params: &Value) -> u64 {
+    let digest = digest::digest(&digest::SHA256, params.to_string().as_bytes());
+    digest.as_ref()[..8].iter().fold(0, |hash, &byte| hash << 8 | u64::from(byte))
+}
+
 fn extract_error(data: &Value) -> CrateDBError {
     let message = data.pointer("/error/message").unwrap().as_str().unwrap();
     let code = data.pointer("/error/code")
@@ -145,33 +248,40 @@ impl<T: Backend + Sized> QueryRunner for DBCluster<T> {
         where SQL: Into<String>,
               S: Serialize
     {
-        let (result, body) = self.execute(sql, false, params);
-        if let Ok(raw) = serde_json::from_str(&body) {
+        self.execute(sql, false, params, |result, body| if let Ok(raw) = serde_json::from_str(&body) {
 
-            let data: Value = raw;
+            let mut data: Value = raw;
             match result {
                 BackendResult::NotFound |
                 BackendResult::NotAuthorized |
+                BackendResult::Conflict |
                 BackendResult::Timeout |
                 BackendResult::Error => Err(extract_error(&data)),
                 BackendResult::Ok => {
-                    if let Some(cols) = data.pointer("/cols")
+                    if let Some(names) = data.pointer("/cols")
                            .and_then(|v| v.as_array())
-                           .and_then(|cols_raw| {
-                        let mut cols = HashMap::with_capacity(cols_raw.len());
-                        for (i, c) in cols_raw.iter().enumerate() {
-                            let _ = match *c {
-                                Value::String(ref name) => cols.insert(name.to_owned(), i),
-                                _ => None,
-                            };
-                        }
-                        Some(cols)
+                           .map(|cols_raw| {
+                        cols_raw.iter()
+                            .map(|c| c.as_str().map(String::from).unwrap_or_else(|| c.to_string()))
+                            .collect()
                     }) {
-                        let rows = data.pointer("/rows").unwrap().as_array().unwrap();
-                        //let cols_raw = cols_raw.as_array().unwrap();
-
-                        let duration = data.pointer("/duration").unwrap().as_f64().unwrap();
-                        Ok((duration, RowIterator::new(rows.clone(), cols)))
+                        let types = data.pointer("/col_types")
+                            .and_then(|v| v.as_array())
+                            .map(|types| types.iter().map(ColumnType::from_value).collect());
+                        let cols = Columns::new(names, types);
+                        let duration = data.pointer("/duration").and_then(|v| v.as_f64());
+                        // moves the rows out of the response instead of copying them
+                        let rows = data.pointer_mut("/rows").map(Value::take);
+                        match (duration, rows) {
+                            (Some(duration), Some(Value::Array(rows))) => {
+                                if rows.iter().all(Value::is_array) {
+                                    Ok((duration, RowIterator::with_columns(rows, cols)))
+                                } else {
+                                    Err(CrateDBError::new("Invalid JSON returned", "401"))
+                                }
+                            }
+                            _ => Err(CrateDBError::new("Invalid JSON returned", "401")),
+                        }
                     } else {
                         Err(CrateDBError::new("Invalid JSON returned", "401"))
                     }
@@ -180,7 +290,7 @@ impl<T: Backend + Sized> QueryRunner for DBCluster<T> {
         } else {
             Err(CrateDBError::new(format!("{}: {}", "Invalid JSON was returned", body),
                                   format!("{}", result as u8)))
-        }
+        })
     }
 
 
@@ -190,25 +300,148 @@ impl<T: Backend + Sized> QueryRunner for DBCluster<T> {
               S: Serialize
     {
 
-        let (result, body) = self.execute(sql, true, Some(params));
-
-        if let Ok(raw) = serde_json::from_str(&body) {
-            let data: Value = raw;
-
-            return match data.pointer("/cols") {
-                       Some(_) => {
-                           let bulk_results = data.pointer("/results").unwrap().as_array().unwrap();
-                           let rowcounts = bulk_results
-                               .into_iter()
-                               .map(|v| v.pointer("/rowcount").unwrap().as_i64().unwrap())
-                               .collect();
-                           let duration = data.pointer("/duration").unwrap().as_f64().unwrap();
-                           Ok((duration, rowcounts))
-                       }
-                       None => Err(extract_error(&data)),
-                   };
+        self.execute(sql, true, Some(params), |result, body| {
+            if let Ok(raw) = serde_json::from_str(&body) {
+                let data: Value = raw;
+
+                return match data.pointer("/cols") {
+                           Some(_) => {
+                               let bulk_results = data.pointer("/results").unwrap().as_array().unwrap();
+                               let rowcounts = bulk_results
+                                   .iter()
+                                   .map(|v| v.pointer("/rowcount").unwrap().as_i64().unwrap())
+                                   .collect();
+                               let duration = data.pointer("/duration").unwrap().as_f64().unwrap();
+                               Ok((duration, rowcounts))
+                           }
+                           None => Err(extract_error(&data)),
+                       };
+            }
+            Err(CrateDBError::new(format!("{}: {}", "Invalid JSON was returned", body),
+                                  format!("{}", result as u8)))
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate hyper;
+
+    use super::*;
+    use self::hyper::Url;
+    use std::sync::{Arc, Mutex};
+    use testing::{Call, Exchange, MockBackend, MockResponse, RecordedRequest, RecordedResponse,
+                  ReplayBackend};
+    use row::Row;
+    use NoParams;
+
+    fn cluster(backend: &MockBackend) -> DBCluster<MockBackend> {
+        DBCluster::with_custom_backend(vec![Url::parse("http://db:4200").unwrap()], backend.clone())
+    }
+
+    #[test]
+    fn slow_queries() {
+        let backend = MockBackend::new();
+        backend.on("select ?", MockResponse::rows(vec!["a"], vec![]).with_duration(12.5));
+        backend.on("select x", MockResponse::error(4043, "Column x unknown"));
+        let reported = Arc::new(Mutex::new(vec![]));
+        let mut c = cluster(&backend);
+        let r = reported.clone();
+        c.set_slow_query_threshold(Duration::from_millis(0),
+                                   move |q| r.lock().unwrap().push(q.clone()));
+
+        c.query("select ?", Some(Box::new(json!(["secret"])))).unwrap();
+        c.query("select ?", Some(Box::new(json!(["other"])))).unwrap();
+        assert!(c.query("select x", None::<Box<NoParams>>).is_err());
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 3);
+        assert_eq!(reported[0].statement, "select ?");
+        assert_eq!(reported[0].node, Some("http://db:4200/".to_string()));
+        assert_eq!(reported[0].server_duration, Some(12.5));
+        assert!(reported[0].params_hash.is_some());
+        assert!(reported[0].params_hash != reported[1].params_hash);
+        assert_eq!(reported[2].params_hash, None);
+        assert_eq!(reported[2].server_duration, None);
+    }
+
+    #[test]
+    fn stable_params_hash() {
+        assert_eq!(hash(&json!(["secret"])), 0x7ed6936249ddb5c8);
+        assert_eq!(hash(&json!({"b": 1, "a": 2})), hash(&json!({"a": 2, "b": 1})));
+    }
+
+    #[test]
+    fn fast_queries() {
+        let backend = MockBackend::new();
+        backend.on("select 1", MockResponse::rows(vec!["1"], vec![]));
+        let reported = Arc::new(Mutex::new(0));
+        let mut c = cluster(&backend);
+        let r = reported.clone();
+        c.set_slow_query_threshold(Duration::from_secs(60), move |_| *r.lock().unwrap() += 1);
+        c.query("select 1", None::<Box<NoParams>>).unwrap();
+        assert_eq!(*reported.lock().unwrap(), 0);
+
+        let r = reported.clone();
+        c.set_slow_query_threshold(Duration::from_millis(0), move |_| *r.lock().unwrap() += 1);
+        c.clear_slow_query_threshold();
+        c.query("select 1", None::<Box<NoParams>>).unwrap();
+        assert_eq!(*reported.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn columns() {
+        let backend = MockBackend::new();
+        backend.on("select id, tags from t",
+                   MockResponse::rows(vec!["id", "tags"], vec![vec![json!(1), json!(["a"])]])
+                       .with_types(vec![json!(10), json!([100, 4])]));
+        let mut c = cluster(&backend);
+
+        let (_, rows) = c.query("select id, tags from t", None::<Box<NoParams>>).unwrap();
+        assert_eq!(rows.columns().names(), &["id".to_string(), "tags".to_string()][..]);
+        assert_eq!(rows.columns().column_type(1),
+                   Some(&ColumnType::Array(Box::new(ColumnType::String))));
+
+        c.set_column_types(true);
+        let (_, mut rows) = c.query("select id, tags from t", None::<Box<NoParams>>).unwrap();
+        let row = rows.next().unwrap();
+        assert_eq!(row.columns().column_type(0), Some(&ColumnType::Long));
+        match backend.calls()[..] {
+            [Call::Sql { url: ref first, .. }, Call::Sql { url: ref second, .. }] => {
+                assert_eq!(first.as_deref(), Some("http://db:4200/_sql"));
+                assert_eq!(second.as_deref(), Some("http://db:4200/_sql?types"));
+            }
+            ref calls => panic!("unexpected calls {:?}", calls),
+        }
+    }
+
+    #[test]
+    fn malformed_rows() {
+        let exchange = |body: &str| {
+            Exchange {
+                request: RecordedRequest::Sql {
+                    url: Some("http://db:4200/_sql".to_string()),
+                    payload: r#"{"stmt":"select a from t"}"#.to_string(),
+                },
+                response: RecordedResponse::Success {
+                    status: 200,
+                    body: body.to_string(),
+                },
+            }
+        };
+        let backend = ReplayBackend::new(vec![exchange(r#"{"cols":["a"],"duration":1.0}"#),
+                                              exchange(r#"{"cols":["a"],"rows":{},"duration":1.0}"#),
+                                              exchange(r#"{"cols":["a"],"rows":[[1],2],"duration":1.0}"#),
+                                              exchange(r#"{"cols":["a"],"rows":[[1],[]],"duration":1.0}"#)]);
+        let c = DBCluster::with_custom_backend(vec![Url::parse("http://db:4200").unwrap()], backend);
+
+        for _ in 0..3 {
+            let err = c.query("select a from t", None::<Box<NoParams>>).unwrap_err();
+            assert_eq!(err.message, "Invalid JSON returned");
         }
-        Err(CrateDBError::new(format!("{}: {}", "Invalid JSON was returned", body),
-                              format!("{}", result as u8)))
+        let (_, rows) = c.query("select a from t", None::<Box<NoParams>>).unwrap();
+        let values: Vec<Vec<Value>> = rows.map(Row::into_values).collect();
+        assert_eq!(values, vec![vec![json!(1)], vec![]]);
     }
 }
@@ -0,0 +1,620 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//!
+//! Test doubles for code that uses the driver. Requires the `testing` feature.
+//!
+//! `MockBackend` answers with scripted responses, `RecordingBackend` and
+//! `ReplayBackend` capture real traffic once and serve it back later, and
+//! `FakeServer` puts a `MockBackend` behind a local HTTP endpoint.
+//!
+//! # Example
+//!
+//! ```rust
+//! # #[macro_use] extern crate serde_json;
+//! # extern crate cratedb;
+//! use cratedb::NoParams;
+//! use cratedb::dbcluster::DBCluster;
+//! use cratedb::row::ByIndex;
+//! use cratedb::sql::QueryRunner;
+//! use cratedb::testing::{MockBackend, MockResponse};
+//!
+//! # fn main() {
+//! let backend = MockBackend::new();
+//! backend.on("select name from sys.nodes",
+//!            MockResponse::rows(vec!["name"], vec![vec![json!("node1")]]));
+//!
+//! let c = DBCluster::with_custom_backend(vec![], backend);
+//! let (_, mut rows) = c.query("select name from sys.nodes", None::<Box<NoParams>>).unwrap();
+//! assert_eq!(rows.next().unwrap().as_string(0), Some("node1".to_string()));
+//! assert_eq!(c.backend.statements(), vec!["select name from sys.nodes"]);
+//! # }
+//! ```
+//!
+
+extern crate serde;
+extern crate serde_json;
+
+mod replay;
+mod server;
+
+pub use self::replay::{RecordingBackend, ReplayBackend, Exchange, RecordedRequest,
+                       RecordedResponse};
+pub use self::server::FakeServer;
+
+use backend::{Backend, BackendResult};
+use error::BackendError;
+use self::serde::Serialize;
+use self::serde_json::Value;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+///
+/// A request the `MockBackend` received, in the order they were made.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Call {
+    /// A SQL request. `args` and `bulk_args` are the parameters as sent.
+    Sql {
+        url: Option<String>,
+        stmt: String,
+        args: Option<Value>,
+        bulk_args: Option<Value>,
+    },
+    /// A blob upload with the complete content that was read.
+    Upload {
+        url: Option<String>,
+        bucket: String,
+        sha1: Vec<u8>,
+        content: Vec<u8>,
+    },
+    /// A blob download.
+    Fetch {
+        url: Option<String>,
+        bucket: String,
+        sha1: Vec<u8>,
+    },
+    /// A blob deletion.
+    Delete {
+        url: Option<String>,
+        bucket: String,
+        sha1: Vec<u8>,
+    },
+}
+
+///
+/// A canned answer to a SQL request, encoded like CrateDB's `_sql` endpoint would.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockResponse {
+    /// A result set, with CrateDB's `col_types` if set.
+    Rows {
+        cols: Vec<String>,
+        col_types: Option<Vec<Value>>,
+        rows: Vec<Vec<Value>>,
+        duration: f64,
+    },
+    /// A bulk result with one row count per parameter set.
+    Bulk { rowcounts: Vec<i64>, duration: f64 },
+    /// An error response from the cluster, with its error code (e.g. 4041).
+    Error {
+        status: BackendResult,
+        code: i64,
+        message: String,
+    },
+    /// A failure to reach the cluster at all.
+    Transport(String),
+}
+
+impl MockResponse {
+    ///
+    /// A result set with the given column names and rows.
+    ///
+    pub fn rows<S: Into<String>>(cols: Vec<S>, rows: Vec<Vec<Value>>) -> MockResponse {
+        MockResponse::Rows {
+            cols: cols.into_iter().map(Into::into).collect(),
+            col_types: None,
+            rows,
+            duration: 0.0,
+        }
+    }
+
+    ///
+    /// A bulk result with the given row counts.
+    ///
+    pub fn bulk(rowcounts: Vec<i64>) -> MockResponse {
+        MockResponse::Bulk {
+            rowcounts,
+            duration: 0.0,
+        }
+    }
+
+    ///
+    /// An error CrateDB reports with the given code, e.g. `4041` for an unknown table.
+    /// The HTTP status is derived from the code's first three digits.
+    ///
+    pub fn error<S: Into<String>>(code: i64, message: S) -> MockResponse {
+        let status = match code / 10 {
+            401 | 403 => BackendResult::NotAuthorized,
+            404 => BackendResult::NotFound,
+            409 => BackendResult::Conflict,
+            _ => BackendResult::Error,
+        };
+        MockResponse::Error {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    ///
+    /// Sets the server-side duration (in ms) of a result set or bulk result.
+    ///
+    pub fn with_duration(mut self, ms: f64) -> MockResponse {
+        match self {
+            MockResponse::Rows { ref mut duration, .. } |
+            MockResponse::Bulk { ref mut duration, .. } => *duration = ms,
+            _ => {}
+        }
+        self
+    }
+
+    ///
+    /// Adds CrateDB's type ids for the columns of a result set, e.g.
+    /// `json!(10)` for a `bigint` or `json!([100, 4])` for an `array(text)`.
+    ///
+    pub fn with_types(mut self, types: Vec<Value>) -> MockResponse {
+        if let MockResponse::Rows { ref mut col_types, .. } = self {
+            *col_types = Some(types);
+        }
+        self
+    }
+
+    fn encode(&self) -> Result<(BackendResult, String), BackendError> {
+        match *self {
+            MockResponse::Rows {
+                ref cols,
+                ref col_types,
+                ref rows,
+                duration,
+            } => {
+                let mut response = json!({
+                                             "cols": cols,
+                                             "rows": rows,
+                                             "rowcount": rows.len(),
+                                             "duration": duration
+                                         });
+                if let Some(ref types) = *col_types {
+                    response["col_types"] = json!(types);
+                }
+                Ok((BackendResult::Ok, response.to_string()))
+            }
+            MockResponse::Bulk {
+                ref rowcounts,
+                duration,
+            } => {
+                let results: Vec<Value> = rowcounts
+                    .iter()
+                    .map(|rowcount| json!({ "rowcount": rowcount }))
+                    .collect();
+                Ok((BackendResult::Ok,
+                    json!({
+                              "cols": [],
+                              "results": results,
+                              "duration": duration
+                          })
+                            .to_string()))
+            }
+            MockResponse::Error {
+                ref status,
+                code,
+                ref message,
+            } => {
+                Ok((status.clone(),
+                    json!({ "error": { "message": message, "code": code } }).to_string()))
+            }
+            MockResponse::Transport(ref description) => Err(BackendError::new(description.clone())),
+        }
+    }
+}
+
+type Matcher = Box<dyn Fn(&str, Option<&Value>) -> bool + Send>;
+
+struct Rule {
+    matcher: Matcher,
+    response: MockResponse,
+}
+
+#[derive(Default)]
+struct State {
+    rules: Vec<Rule>,
+    calls: Vec<Call>,
+    blobs: HashMap<(String, Vec<u8>), Vec<u8>>,
+    blob_status: Option<BackendResult>,
+    transport_error: Option<String>,
+}
+
+///
+/// A scriptable `Backend` for tests. SQL requests are answered with the response
+/// of the first registered rule that matches, blobs are kept in memory, and every
+/// call is recorded. Statements without a matching rule fail with a transport
+/// error, so a missing rule doesn't go unnoticed.
+///
+/// Clones share their state, so a test can keep a handle to a backend that has
+/// been moved into a `DBCluster`.
+///
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    state: Arc<Mutex<State>>,
+}
+
+impl MockBackend {
+    pub fn new() -> MockBackend {
+        MockBackend::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // a panicking test thread must not hide the calls from the others
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    ///
+    /// Answers the statement (compared without surrounding whitespace) with the
+    /// response, regardless of its parameters.
+    ///
+    pub fn on<S: Into<String>>(&self, stmt: S, response: MockResponse) -> &MockBackend {
+        let stmt = stmt.into();
+        self.on_match(move |s, _| s.trim() == stmt.trim(), response)
+    }
+
+    ///
+    /// Answers the statement with the response only if it's sent with these
+    /// parameters (or bulk parameters).
+    ///
+    /// # Panics
+    /// If the parameters can't be serialized.
+    ///
+    pub fn on_params<S, P>(&self, stmt: S, params: &P, response: MockResponse) -> &MockBackend
+        where S: Into<String>,
+              P: Serialize
+    {
+        let stmt = stmt.into();
+        let params = serde_json::to_value(params).expect("Parameters can't be serialized");
+        self.on_match(move |s, p| s.trim() == stmt.trim() && p == Some(&params),
+                      response)
+    }
+
+    ///
+    /// Answers every request the matcher accepts with the response. The matcher
+    /// receives the statement and its parameters (or bulk parameters).
+    ///
+    pub fn on_match<F>(&self, matcher: F, response: MockResponse) -> &MockBackend
+        where F: Fn(&str, Option<&Value>) -> bool + Send + 'static
+    {
+        self.state()
+            .rules
+            .push(Rule {
+                      matcher: Box::new(matcher),
+                      response,
+                  });
+        self
+    }
+
+    ///
+    /// Every request made so far.
+    ///
+    pub fn calls(&self) -> Vec<Call> {
+        self.state().calls.clone()
+    }
+
+    ///
+    /// The statements of all SQL requests made so far.
+    ///
+    pub fn statements(&self) -> Vec<String> {
+        self.state()
+            .calls
+            .iter()
+            .filter_map(|call| match *call {
+                            Call::Sql { ref stmt, .. } => Some(stmt.clone()),
+                            _ => None,
+                        })
+            .collect()
+    }
+
+    ///
+    /// Forgets all recorded calls.
+    ///
+    pub fn clear_calls(&self) {
+        self.state().calls.clear();
+    }
+
+    ///
+    /// Stores a blob, as if it had been uploaded before.
+    ///
+    pub fn insert_blob<S: Into<String>>(&self, bucket: S, sha1: &[u8], content: Vec<u8>) {
+        self.state()
+            .blobs
+            .insert((bucket.into(), sha1.to_vec()), content);
+    }
+
+    ///
+    /// The content of a stored blob.
+    ///
+    pub fn blob(&self, bucket: &str, sha1: &[u8]) -> Option<Vec<u8>> {
+        self.state()
+            .blobs
+            .get(&(bucket.to_string(), sha1.to_vec()))
+            .cloned()
+    }
+
+    ///
+    /// Makes every blob operation report this status, or behave normally again with `None`.
+    ///
+    pub fn set_blob_status(&self, status: Option<BackendResult>) {
+        self.state().blob_status = status;
+    }
+
+    ///
+    /// Makes every request (SQL and blob) fail as if the cluster was unreachable,
+    /// or behave normally again with `None`.
+    ///
+    pub fn set_transport_error<S: Into<String>>(&self, description: Option<S>) {
+        self.state().transport_error = description.map(Into::into);
+    }
+
+    // Records the call and returns the simulated failure, if any.
+    fn record(&self, call: Call) -> Result<Option<BackendResult>, BackendError> {
+        let mut state = self.state();
+        state.calls.push(call);
+        match state.transport_error {
+            Some(ref description) => Err(BackendError::new(description.clone())),
+            None => Ok(state.blob_status.clone()),
+        }
+    }
+}
+
+impl Backend for MockBackend {
+    fn execute(&self,
+               to: Option<String>,
+               payload: String)
+               -> Result<(BackendResult, String), BackendError> {
+        let request: Value = serde_json::from_str(&payload)
+            .map_err(|e| BackendError::new(format!("Invalid SQL request '{}': {}", payload, e)))?;
+        let stmt = request["stmt"].as_str().unwrap_or_default().to_string();
+        let args = request.get("args").cloned();
+        let bulk_args = request.get("bulk_args").cloned();
+        let params = args.as_ref().or(bulk_args.as_ref()).cloned();
+
+        self.record(Call::Sql {
+                        url: to,
+                        stmt: stmt.clone(),
+                        args,
+                        bulk_args,
+                    })?;
+        let state = self.state();
+        match state
+                  .rules
+                  .iter()
+                  .find(|rule| (rule.matcher)(&stmt, params.as_ref())) {
+            Some(rule) => rule.response.encode(),
+            None => Err(BackendError::new(format!("No mock response for statement '{}'", stmt))),
+        }
+    }
+
+    fn upload_blob(&self,
+                   to: Option<String>,
+                   bucket: &str,
+                   sha1: &[u8],
+                   f: &mut dyn Read)
+                   -> Result<BackendResult, BackendError> {
+        let mut content = vec![];
+        f.read_to_end(&mut content).map_err(BackendError::from_io)?;
+        if let Some(status) = self.record(Call::Upload {
+                                              url: to,
+                                              bucket: bucket.to_string(),
+                                              sha1: sha1.to_vec(),
+                                              content: content.clone(),
+                                          })? {
+            return Ok(status);
+        }
+        match self.state()
+                  .blobs
+                  .entry((bucket.to_string(), sha1.to_vec())) {
+            Entry::Occupied(_) => Ok(BackendResult::Conflict),
+            Entry::Vacant(entry) => {
+                entry.insert(content);
+                Ok(BackendResult::Ok)
+            }
+        }
+    }
+
+    fn delete_blob(&self,
+                   to: Option<String>,
+                   bucket: &str,
+                   sha1: &[u8])
+                   -> Result<BackendResult, BackendError> {
+        if let Some(status) = self.record(Call::Delete {
+                                              url: to,
+                                              bucket: bucket.to_string(),
+                                              sha1: sha1.to_vec(),
+                                          })? {
+            return Ok(status);
+        }
+        match self.state()
+                  .blobs
+                  .remove(&(bucket.to_string(), sha1.to_vec())) {
+            Some(_) => Ok(BackendResult::Ok),
+            None => Ok(BackendResult::NotFound),
+        }
+    }
+
+    fn fetch_blob(&self,
+                  to: Option<String>,
+                  bucket: &str,
+                  sha1: &[u8])
+                  -> Result<(BackendResult, Box<dyn Read + Send>), BackendError> {
+        if let Some(status) = self.record(Call::Fetch {
+                                              url: to,
+                                              bucket: bucket.to_string(),
+                                              sha1: sha1.to_vec(),
+                                          })? {
+            return Ok((status, Box::new(Cursor::new(vec![]))));
+        }
+        match self.blob(bucket, sha1) {
+            Some(content) => Ok((BackendResult::Ok, Box::new(Cursor::new(content)))),
+            None => Ok((BackendResult::NotFound, Box::new(Cursor::new(vec![])))),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use NoParams;
+    use blob::BlobContainer;
+    use dbcluster::DBCluster;
+    use error::BlobError;
+    use row::ByIndex;
+    use sql::QueryRunner;
+
+    fn cluster(backend: &MockBackend) -> DBCluster<MockBackend> {
+        DBCluster::with_custom_backend(vec![], backend.clone())
+    }
+
+    #[test]
+    fn canned_rows() {
+        let backend = MockBackend::new();
+        backend.on("select name, port from sys.nodes",
+                   MockResponse::rows(vec!["name", "port"],
+                                      vec![vec![json!("n1"), json!(4200)],
+                                           vec![json!("n2"), json!(4201)]])
+                           .with_duration(1.5));
+        let c = cluster(&backend);
+
+        let (duration, rows) = c.query(" select name, port from sys.nodes ", None::<Box<NoParams>>)
+            .unwrap();
+        assert_eq!(duration, 1.5);
+        let ports: Vec<i64> = rows.map(|r| r.as_i64(1).unwrap()).collect();
+        assert_eq!(ports, vec![4200, 4201]);
+    }
+
+    #[test]
+    fn match_parameters() {
+        let backend = MockBackend::new();
+        backend
+            .on_params("select name from t where id = ?",
+                       &[1],
+                       MockResponse::rows(vec!["name"], vec![vec![json!("one")]]))
+            .on("select name from t where id = ?",
+                MockResponse::rows(vec!["name"], vec![]))
+            .on_params("insert into t (id) values (?)",
+                       &[[1], [2]],
+                       MockResponse::bulk(vec![1, 1]));
+        let c = cluster(&backend);
+
+        let (_, rows) = c.query("select name from t where id = ?", Some(Box::new([1])))
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        let (_, rows) = c.query("select name from t where id = ?", Some(Box::new([2])))
+            .unwrap();
+        assert_eq!(rows.len(), 0);
+        let (_, counts) = c.bulk_query("insert into t (id) values (?)",
+                                       Box::new([[1], [2]]))
+            .unwrap();
+        assert_eq!(counts, vec![1, 1]);
+
+        assert_eq!(backend.calls()[0],
+                   Call::Sql {
+                       url: None,
+                       stmt: "select name from t where id = ?".to_string(),
+                       args: Some(json!([1])),
+                       bulk_args: None,
+                   });
+        assert_eq!(backend.statements().len(), 3);
+    }
+
+    #[test]
+    fn errors() {
+        let backend = MockBackend::new();
+        backend.on("select * from missing",
+                   MockResponse::error(4041, "Relation 'missing' unknown"));
+        let c = cluster(&backend);
+
+        let e = c.query("select * from missing", None::<Box<NoParams>>)
+            .unwrap_err();
+        assert_eq!(e.code, "4041");
+        assert_eq!(e.message, "Relation 'missing' unknown");
+
+        let e = c.query("select 1", None::<Box<NoParams>>).unwrap_err();
+        assert!(e.message.ends_with("No mock response for statement 'select 1'"));
+
+        backend.set_transport_error(Some("connection refused"));
+        let e = c.query("select * from missing", None::<Box<NoParams>>)
+            .unwrap_err();
+        assert!(e.message.ends_with("connection refused"));
+        assert_eq!(backend.calls().len(), 3);
+    }
+
+    #[test]
+    fn blob_storage() {
+        let backend = MockBackend::new();
+        let c = cluster(&backend);
+
+        let blob_ref = c.put("b", &mut Cursor::new(b"content")).unwrap();
+        assert_eq!(backend.blob("b", &blob_ref.sha1), Some(b"content".to_vec()));
+        match c.put("b", &mut Cursor::new(b"content")) {
+            Err(BlobError::Action(e)) => assert_eq!(e.code, "409"),
+            _ => panic!("The blob was uploaded twice"),
+        }
+
+        let mut content = vec![];
+        c.get(&blob_ref)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, b"content");
+
+        c.delete(blob_ref.clone()).unwrap();
+        match c.get(&blob_ref) {
+            Err(BlobError::Action(e)) => assert_eq!(e.code, "404"),
+            _ => panic!("The blob wasn't deleted"),
+        }
+
+        backend.insert_blob("b", &blob_ref.sha1, b"again".to_vec());
+        backend.set_blob_status(Some(BackendResult::NotAuthorized));
+        match c.delete(blob_ref.clone()) {
+            Err(BlobError::Action(e)) => assert_eq!(e.code, "403"),
+            _ => panic!("The failure wasn't simulated"),
+        }
+        backend.set_blob_status(None);
+        backend.set_transport_error(Some("connection refused"));
+        match c.get(&blob_ref) {
+            Err(BlobError::Transport(e)) => assert_eq!(e.description, "connection refused"),
+            _ => panic!("The failure wasn't simulated"),
+        }
+
+        let calls = backend.calls();
+        assert_eq!(calls.len(), 7);
+        match calls[0] {
+            Call::Upload { ref bucket, ref content, .. } => {
+                assert_eq!(bucket, "b");
+                assert_eq!(content, b"content");
+            }
+            ref other => panic!("Unexpected call {:?}", other),
+        }
+    }
+}
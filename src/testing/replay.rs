@@ -0,0 +1,527 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate hex;
+extern crate hyper;
+extern crate serde_json;
+
+use backend::{Backend, BackendResult};
+use common::to_hex_string;
+use error::BackendError;
+use self::hex::FromHex;
+use self::hyper::Url;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Write};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+///
+/// A request as it was passed to the recorded backend. Blob hashes and contents
+/// are hex-encoded.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordedRequest {
+    Sql { url: Option<String>, payload: String },
+    Upload {
+        url: Option<String>,
+        bucket: String,
+        sha1: String,
+        content: String,
+    },
+    Fetch {
+        url: Option<String>,
+        bucket: String,
+        sha1: String,
+    },
+    Delete {
+        url: Option<String>,
+        bucket: String,
+        sha1: String,
+    },
+}
+
+impl RecordedRequest {
+    // Requests match regardless of the node they were sent to, since the
+    // cluster picks one at random.
+    fn matches(&self, other: &RecordedRequest) -> bool {
+        fn path(url: &Option<String>) -> Option<String> {
+            url.as_ref()
+                .map(|u| Url::parse(u).map(|u| u.path().to_string()).unwrap_or_else(|_| u.clone()))
+        }
+        fn without_node(request: &RecordedRequest) -> RecordedRequest {
+            let mut request = request.clone();
+            match request {
+                RecordedRequest::Sql { ref mut url, .. } |
+                RecordedRequest::Upload { ref mut url, .. } |
+                RecordedRequest::Fetch { ref mut url, .. } |
+                RecordedRequest::Delete { ref mut url, .. } => *url = path(url),
+            }
+            request
+        }
+        without_node(self) == without_node(other)
+    }
+}
+
+///
+/// The backend's answer to a recorded request. The body is the SQL response, or
+/// the hex-encoded content of a fetched blob.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum RecordedResponse {
+    Success { status: u16, body: String },
+    Failure { description: String },
+}
+
+///
+/// A request and its response. Recordings are stored as one JSON-encoded
+/// exchange per line.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+fn status_code(status: BackendResult) -> u16 {
+    status as u16
+}
+
+fn status_of(code: u16) -> BackendResult {
+    match code {
+        200 => BackendResult::Ok,
+        403 => BackendResult::NotAuthorized,
+        404 => BackendResult::NotFound,
+        409 => BackendResult::Conflict,
+        504 => BackendResult::Timeout,
+        _ => BackendResult::Error,
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+///
+/// Passes every request on to another backend and records it together with the
+/// response, to be saved and served by a `ReplayBackend` later. Blobs are
+/// buffered in memory.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let c = DBCluster::with_custom_backend(nodes, RecordingBackend::new(HTTPBackend::new()));
+/// // ... run the queries ...
+/// c.backend.save("tests/recordings/report.jsonl").unwrap();
+/// ```
+///
+pub struct RecordingBackend<B: Backend> {
+    inner: B,
+    exchanges: Mutex<Vec<Exchange>>,
+}
+
+impl<B: Backend> RecordingBackend<B> {
+    pub fn new(inner: B) -> RecordingBackend<B> {
+        RecordingBackend {
+            inner,
+            exchanges: Mutex::new(vec![]),
+        }
+    }
+
+    ///
+    /// The exchanges recorded so far.
+    ///
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        lock(&self.exchanges).clone()
+    }
+
+    ///
+    /// Writes the exchanges recorded so far to a file, one JSON object per line.
+    ///
+    /// # Errors
+    /// If the file can't be written.
+    ///
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        for exchange in lock(&self.exchanges).iter() {
+            serde_json::to_writer(&mut file, exchange)?;
+            file.write_all(b"\n")?;
+        }
+        file.flush()
+    }
+
+    ///
+    /// The wrapped backend.
+    ///
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn record(&self, request: RecordedRequest, response: RecordedResponse) {
+        lock(&self.exchanges).push(Exchange { request, response });
+    }
+}
+
+fn recorded<T, F>(result: &Result<T, BackendError>, success: F) -> RecordedResponse
+    where F: Fn(&T) -> RecordedResponse
+{
+    match *result {
+        Ok(ref value) => success(value),
+        Err(ref e) => RecordedResponse::Failure { description: e.description.clone() },
+    }
+}
+
+impl<B: Backend> Backend for RecordingBackend<B> {
+    fn execute(&self,
+               to: Option<String>,
+               payload: String)
+               -> Result<(BackendResult, String), BackendError> {
+        let request = RecordedRequest::Sql {
+            url: to.clone(),
+            payload: payload.clone(),
+        };
+        let result = self.inner.execute(to, payload);
+        self.record(request,
+                    recorded(&result, |(status, body)| {
+            RecordedResponse::Success {
+                status: status_code(status.clone()),
+                body: body.clone(),
+            }
+        }));
+        result
+    }
+
+    fn upload_blob(&self,
+                   to: Option<String>,
+                   bucket: &str,
+                   sha1: &[u8],
+                   f: &mut dyn Read)
+                   -> Result<BackendResult, BackendError> {
+        let mut content = vec![];
+        f.read_to_end(&mut content).map_err(BackendError::from_io)?;
+        let request = RecordedRequest::Upload {
+            url: to.clone(),
+            bucket: bucket.to_string(),
+            sha1: to_hex_string(sha1),
+            content: to_hex_string(&content),
+        };
+        let result = self.inner
+            .upload_blob(to, bucket, sha1, &mut Cursor::new(content));
+        self.record(request,
+                    recorded(&result, |status| {
+            RecordedResponse::Success {
+                status: status_code(status.clone()),
+                body: String::new(),
+            }
+        }));
+        result
+    }
+
+    fn delete_blob(&self,
+                   to: Option<String>,
+                   bucket: &str,
+                   sha1: &[u8])
+                   -> Result<BackendResult, BackendError> {
+        let request = RecordedRequest::Delete {
+            url: to.clone(),
+            bucket: bucket.to_string(),
+            sha1: to_hex_string(sha1),
+        };
+        let result = self.inner.delete_blob(to, bucket, sha1);
+        self.record(request,
+                    recorded(&result, |status| {
+            RecordedResponse::Success {
+                status: status_code(status.clone()),
+                body: String::new(),
+            }
+        }));
+        result
+    }
+
+    fn fetch_blob(&self,
+                  to: Option<String>,
+                  bucket: &str,
+                  sha1: &[u8])
+                  -> Result<(BackendResult, Box<dyn Read + Send>), BackendError> {
+        let request = RecordedRequest::Fetch {
+            url: to.clone(),
+            bucket: bucket.to_string(),
+            sha1: to_hex_string(sha1),
+        };
+        let result = self.inner
+            .fetch_blob(to, bucket, sha1)
+            .and_then(|(status, mut content)| {
+                          let mut buffer = vec![];
+                          content
+                              .read_to_end(&mut buffer)
+                              .map_err(BackendError::from_io)?;
+                          Ok((status, buffer))
+                      });
+        self.record(request,
+                    recorded(&result, |(status, content)| {
+            RecordedResponse::Success {
+                status: status_code(status.clone()),
+                body: to_hex_string(content),
+            }
+        }));
+        result.map(|(status, content)| (status, Box::new(Cursor::new(content)) as Box<dyn Read + Send>))
+    }
+}
+
+///
+/// Serves the exchanges of a recording. Every request is answered with the
+/// response of the first unused exchange with the same request, whichever node
+/// it is sent to. A request that isn't part of the recording panics, so that a
+/// test doesn't silently run against missing data.
+///
+pub struct ReplayBackend {
+    exchanges: Mutex<Vec<Option<Exchange>>>,
+}
+
+impl ReplayBackend {
+    pub fn new(exchanges: Vec<Exchange>) -> ReplayBackend {
+        ReplayBackend { exchanges: Mutex::new(exchanges.into_iter().map(Some).collect()) }
+    }
+
+    ///
+    /// Reads a recording written by `RecordingBackend::save()`.
+    ///
+    /// # Errors
+    /// If the file can't be read or a line isn't a valid exchange.
+    ///
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<ReplayBackend> {
+        let mut exchanges = vec![];
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                exchanges.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(ReplayBackend::new(exchanges))
+    }
+
+    ///
+    /// The exchanges that haven't been replayed yet.
+    ///
+    pub fn remaining(&self) -> Vec<Exchange> {
+        lock(&self.exchanges)
+            .iter()
+            .filter_map(|e| e.clone())
+            .collect()
+    }
+
+    ///
+    /// Panics unless every exchange has been replayed.
+    ///
+    pub fn assert_exhausted(&self) {
+        let remaining = self.remaining();
+        if !remaining.is_empty() {
+            panic!("{} recorded requests were never made: {:?}",
+                   remaining.len(),
+                   remaining
+                       .iter()
+                       .map(|e| &e.request)
+                       .collect::<Vec<_>>());
+        }
+    }
+
+    fn replay(&self, request: RecordedRequest) -> Result<(BackendResult, String), BackendError> {
+        let mut exchanges = lock(&self.exchanges);
+        let found = exchanges
+            .iter_mut()
+            .find(|e| e.as_ref().is_some_and(|e| e.request.matches(&request)))
+            .and_then(|e| e.take());
+        // release the lock before panicking, so other threads see the remaining exchanges
+        drop(exchanges);
+        match found {
+            Some(Exchange { response: RecordedResponse::Success { status, body }, .. }) => {
+                Ok((status_of(status), body))
+            }
+            Some(Exchange { response: RecordedResponse::Failure { description }, .. }) => {
+                Err(BackendError::new(description))
+            }
+            None => panic!("Unexpected request, not in the recording: {:?}", request),
+        }
+    }
+}
+
+impl Backend for ReplayBackend {
+    fn execute(&self,
+               to: Option<String>,
+               payload: String)
+               -> Result<(BackendResult, String), BackendError> {
+        self.replay(RecordedRequest::Sql { url: to, payload })
+    }
+
+    fn upload_blob(&self,
+                   to: Option<String>,
+                   bucket: &str,
+                   sha1: &[u8],
+                   f: &mut dyn Read)
+                   -> Result<BackendResult, BackendError> {
+        let mut content = vec![];
+        f.read_to_end(&mut content).map_err(BackendError::from_io)?;
+        self.replay(RecordedRequest::Upload {
+                        url: to,
+                        bucket: bucket.to_string(),
+                        sha1: to_hex_string(sha1),
+                        content: to_hex_string(&content),
+                    })
+            .map(|(status, _)| status)
+    }
+
+    fn delete_blob(&self,
+                   to: Option<String>,
+                   bucket: &str,
+                   sha1: &[u8])
+                   -> Result<BackendResult, BackendError> {
+        self.replay(RecordedRequest::Delete {
+                        url: to,
+                        bucket: bucket.to_string(),
+                        sha1: to_hex_string(sha1),
+                    })
+            .map(|(status, _)| status)
+    }
+
+    fn fetch_blob(&self,
+                  to: Option<String>,
+                  bucket: &str,
+                  sha1: &[u8])
+                  -> Result<(BackendResult, Box<dyn Read + Send>), BackendError> {
+        let (status, body) = self.replay(RecordedRequest::Fetch {
+                                             url: to,
+                                             bucket: bucket.to_string(),
+                                             sha1: to_hex_string(sha1),
+                                         })?;
+        let content = Vec::from_hex(body)
+            .map_err(|e| BackendError::new(format!("Invalid blob in recording: {:?}", e)))?;
+        Ok((status, Box::new(Cursor::new(content))))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use NoParams;
+    use blob::BlobContainer;
+    use dbcluster::DBCluster;
+    use row::ByIndex;
+    use sql::QueryRunner;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use testing::{MockBackend, MockResponse};
+
+    fn nodes() -> Vec<Url> {
+        vec![Url::parse("http://node1:4200/").unwrap(),
+             Url::parse("http://node2:4200/").unwrap()]
+    }
+
+    fn recording_file(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("cratedb-{}-{}.jsonl", name, ::std::process::id()))
+    }
+
+    fn record(path: &Path) {
+        let mock = MockBackend::new();
+        mock.on("select name from t",
+                MockResponse::rows(vec!["name"], vec![vec![json!("a")], vec![json!("b")]]))
+            .on("select * from missing",
+                MockResponse::error(4041, "Relation 'missing' unknown"));
+        let c = DBCluster::with_custom_backend(nodes(), RecordingBackend::new(mock));
+
+        assert_eq!(c.query("select name from t", None::<Box<NoParams>>)
+                       .unwrap()
+                       .1
+                       .len(),
+                   2);
+        assert!(c.query("select * from missing", None::<Box<NoParams>>).is_err());
+        let blob = c.put("b", &mut Cursor::new(b"content")).unwrap();
+        let mut content = vec![];
+        c.get(&blob).unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"content");
+        c.delete(blob).unwrap();
+        c.backend.inner.set_transport_error(Some("connection refused"));
+        assert!(c.query("select name from t", None::<Box<NoParams>>).is_err());
+
+        assert_eq!(c.backend.exchanges().len(), 6);
+        c.backend.save(path).unwrap();
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let path = recording_file("record_and_replay");
+        record(&path);
+
+        let exchanges: Vec<Exchange> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        match exchanges[2].request {
+            RecordedRequest::Upload { ref bucket, ref content, .. } => {
+                assert_eq!(bucket, "b");
+                assert_eq!(content, &to_hex_string(b"content"));
+            }
+            ref other => panic!("Unexpected request {:?}", other),
+        }
+
+        let c = DBCluster::with_custom_backend(nodes(), ReplayBackend::load(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let (_, rows) = c.query("select name from t", None::<Box<NoParams>>).unwrap();
+        let names: Vec<String> = rows.map(|r| r.as_string(0).unwrap()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(c.query("select * from missing", None::<Box<NoParams>>)
+                       .unwrap_err()
+                       .code,
+                   "4041");
+        let blob = c.put("b", &mut Cursor::new(b"content")).unwrap();
+        let mut content = vec![];
+        c.get(&blob).unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"content");
+        c.delete(blob).unwrap();
+        assert_eq!(c.backend.remaining().len(), 1);
+
+        // the second, failed attempt of the same statement
+        let e = c.query("select name from t", None::<Box<NoParams>>).unwrap_err();
+        assert!(e.message.ends_with("connection refused"));
+        c.backend.assert_exhausted();
+    }
+
+    #[test]
+    #[should_panic(expected = "Unexpected request")]
+    fn unexpected_request() {
+        let c = DBCluster::with_custom_backend(nodes(), ReplayBackend::new(vec![]));
+        let _ = c.query("select 1", None::<Box<NoParams>>);
+    }
+
+    #[test]
+    #[should_panic(expected = "1 recorded requests were never made")]
+    fn unused_exchange() {
+        let backend = ReplayBackend::new(vec![Exchange {
+                                                  request: RecordedRequest::Delete {
+                                                      url: None,
+                                                      bucket: "b".to_string(),
+                                                      sha1: "00".to_string(),
+                                                  },
+                                                  response: RecordedResponse::Success {
+                                                      status: 200,
+                                                      body: String::new(),
+                                                  },
+                                              }]);
+        backend.assert_exhausted();
+    }
+}
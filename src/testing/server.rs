@@ -0,0 +1,352 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate flate2;
+extern crate hex;
+extern crate hyper;
+
+use backend::{Backend, BackendResult};
+use common::sha1_digest;
+use self::flate2::read::GzDecoder;
+use self::hex::FromHex;
+use self::hyper::header::{ContentEncoding, Encoding, Host};
+use self::hyper::method::Method;
+use self::hyper::server::{Listening, Request, Response, Server};
+use self::hyper::status::StatusCode;
+use self::hyper::uri::RequestUri;
+use super::MockBackend;
+use std::io::{self, Cursor, Read};
+
+///
+/// An in-process HTTP server that speaks CrateDB's `_sql` and `_blobs` protocol,
+/// so that the complete driver, including `HTTPBackend`, can be tested offline.
+///
+/// SQL statements are answered by a `MockBackend`, which is also where blobs are
+/// stored: uploads answer `201 Created` (`409 Conflict` for existing blobs,
+/// `400 Bad Request` if the content doesn't match the digest), downloads `200 OK`
+/// and deletions `204 No Content`, or `404 Not Found` for missing blobs.
+///
+/// # Example
+///
+/// ```rust
+/// # #[macro_use] extern crate serde_json;
+/// # extern crate cratedb;
+/// use cratedb::{Cluster, NoParams};
+/// use cratedb::sql::QueryRunner;
+/// use cratedb::testing::{FakeServer, MockResponse};
+///
+/// # fn main() {
+/// let server = FakeServer::start().unwrap();
+/// server.backend().on("select 1", MockResponse::rows(vec!["1"], vec![vec![json!(1)]]));
+///
+/// let c: Cluster = Cluster::from_string(server.url()).unwrap();
+/// assert_eq!(c.query("select 1", None::<Box<NoParams>>).unwrap().1.len(), 1);
+/// # }
+/// ```
+///
+pub struct FakeServer {
+    url: String,
+    backend: MockBackend,
+    listening: Listening,
+}
+
+impl FakeServer {
+    ///
+    /// Starts a server on a free port on localhost.
+    ///
+    /// # Errors
+    /// If no port can be bound.
+    ///
+    pub fn start() -> io::Result<FakeServer> {
+        let backend = MockBackend::new();
+        let mut server = Server::http("127.0.0.1:0")
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        // HTTPBackend opens a connection per request, idle ones would only tie up threads
+        server.keep_alive(None);
+        let handler_backend = backend.clone();
+        let listening = server
+            .handle(move |req: Request, res: Response| handle(&handler_backend, req, res))
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(FakeServer {
+               url: format!("http://127.0.0.1:{}/", listening.socket.port()),
+               backend,
+               listening,
+           })
+    }
+
+    ///
+    /// The base URL of the server, e.g. `http://127.0.0.1:41234/`.
+    ///
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    ///
+    /// The backend that answers the requests. Script SQL responses, store blobs,
+    /// simulate failures and inspect the requests with it.
+    ///
+    pub fn backend(&self) -> &MockBackend {
+        &self.backend
+    }
+}
+
+impl Drop for FakeServer {
+    fn drop(&mut self) {
+        // hyper can't stop the listener, but this keeps dropping it from
+        // blocking until it does
+        let _ = self.listening.close();
+    }
+}
+
+fn handle(backend: &MockBackend, mut req: Request, mut res: Response) {
+    let path = match req.uri {
+        RequestUri::AbsolutePath(ref path) => path.split('?').next().unwrap_or("").to_string(),
+        _ => String::new(),
+    };
+    let url = req.headers
+        .get::<Host>()
+        .map(|host| match host.port {
+                 Some(port) => format!("http://{}:{}{}", host.hostname, port, path),
+                 None => format!("http://{}{}", host.hostname, path),
+             });
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let gzipped = req.headers
+        .get::<ContentEncoding>()
+        .is_some_and(|e| e.contains(&Encoding::Gzip));
+
+    let mut body = vec![];
+    let read = if gzipped {
+        GzDecoder::new(&mut req).read_to_end(&mut body)
+    } else {
+        req.read_to_end(&mut body)
+    };
+    if let Err(e) = read {
+        return respond(res, StatusCode::BadRequest, &error_body(4000, &e.to_string()));
+    }
+
+    let method = req.method.clone();
+    let (status, content) = match (method, segments.as_slice()) {
+        (Method::Post, ["_sql"]) => {
+            match backend.execute(url, String::from_utf8_lossy(&body).into_owned()) {
+                Ok((result, response)) => (http_status(result, StatusCode::Ok), response.into_bytes()),
+                Err(e) => (StatusCode::InternalServerError, error_body(5000, &e.description)),
+            }
+        }
+        (Method::Put, ["_blobs", bucket, digest]) => {
+            match Vec::from_hex(*digest) {
+                Ok(ref sha1) if sha1_digest(&mut Cursor::new(&body)).ok().as_ref() == Some(sha1) => {
+                    match backend.upload_blob(url, bucket, sha1, &mut Cursor::new(body)) {
+                        Ok(result) => (http_status(result, StatusCode::Created), vec![]),
+                        Err(e) => (StatusCode::InternalServerError, error_body(5000, &e.description)),
+                    }
+                }
+                _ => {
+                    (StatusCode::BadRequest,
+                     error_body(4000, &format!("Digest '{}' doesn't match the content", digest)))
+                }
+            }
+        }
+        (Method::Get, ["_blobs", bucket, digest]) => {
+            let fetched = Vec::from_hex(*digest)
+                .map_err(|_| "Invalid digest".to_string())
+                .and_then(|sha1| {
+                              backend
+                                  .fetch_blob(url, bucket, &sha1)
+                                  .map_err(|e| e.description)
+                          });
+            match fetched {
+                Ok((result, mut reader)) => {
+                    let mut content = vec![];
+                    let _ = reader.read_to_end(&mut content);
+                    (http_status(result, StatusCode::Ok), content)
+                }
+                Err(description) => (StatusCode::InternalServerError, error_body(5000, &description)),
+            }
+        }
+        (Method::Delete, ["_blobs", bucket, digest]) => {
+            let deleted = Vec::from_hex(*digest)
+                .map_err(|_| "Invalid digest".to_string())
+                .and_then(|sha1| {
+                              backend
+                                  .delete_blob(url, bucket, &sha1)
+                                  .map_err(|e| e.description)
+                          });
+            match deleted {
+                Ok(result) => (http_status(result, StatusCode::NoContent), vec![]),
+                Err(description) => (StatusCode::InternalServerError, error_body(5000, &description)),
+            }
+        }
+        _ => (StatusCode::NotFound, error_body(4040, &format!("No handler for {}", path))),
+    };
+    if status == StatusCode::NoContent {
+        *res.status_mut() = status;
+        let _ = res.start().and_then(|r| r.end());
+    } else {
+        respond(res, status, &content);
+    }
+}
+
+fn respond(mut res: Response, status: StatusCode, body: &[u8]) {
+    *res.status_mut() = status;
+    let _ = res.send(body);
+}
+
+fn http_status(result: BackendResult, success: StatusCode) -> StatusCode {
+    match result {
+        BackendResult::Ok => success,
+        BackendResult::NotFound => StatusCode::NotFound,
+        BackendResult::NotAuthorized => StatusCode::Unauthorized,
+        BackendResult::Conflict => StatusCode::Conflict,
+        BackendResult::Timeout => StatusCode::GatewayTimeout,
+        BackendResult::Error => StatusCode::InternalServerError,
+    }
+}
+
+fn error_body(code: i64, message: &str) -> Vec<u8> {
+    json!({ "error": { "message": message, "code": code } })
+        .to_string()
+        .into_bytes()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Cluster, NoParams};
+    use blob::{BlobContainer, BlobRef};
+    use config::{ClusterConfig, CompressionConfig};
+    use error::BlobError;
+    use row::ByIndex;
+    use sql::QueryRunner;
+    use testing::{Call, MockResponse};
+
+    fn blob_error_code<T>(result: Result<T, BlobError>) -> String {
+        match result {
+            Err(BlobError::Action(e)) => e.code,
+            Err(BlobError::Transport(e)) => panic!("Transport error {}", e.description),
+            Ok(_) => panic!("The operation succeeded"),
+        }
+    }
+
+    #[test]
+    fn sql() {
+        let server = FakeServer::start().unwrap();
+        server
+            .backend()
+            .on("select name from sys.nodes",
+                MockResponse::rows(vec!["name"], vec![vec![json!("n1")]]).with_duration(0.5))
+            .on("select * from missing",
+                MockResponse::error(4041, "Relation 'missing' unknown"))
+            .on("insert into t (a) values (?)", MockResponse::bulk(vec![1, 1]));
+        let c: Cluster = Cluster::from_string(server.url()).unwrap();
+
+        let (duration, mut rows) = c.query("select name from sys.nodes", None::<Box<NoParams>>)
+            .unwrap();
+        assert_eq!(duration, 0.5);
+        assert_eq!(rows.next().unwrap().as_string(0), Some("n1".to_string()));
+
+        let e = c.query("select * from missing", None::<Box<NoParams>>)
+            .unwrap_err();
+        assert_eq!(e.code, "4041");
+        assert_eq!(e.message, "Relation 'missing' unknown");
+
+        let (_, counts) = c.bulk_query("insert into t (a) values (?)", Box::new([[1], [2]]))
+            .unwrap();
+        assert_eq!(counts, vec![1, 1]);
+
+        match server.backend().calls()[2] {
+            Call::Sql { ref url, ref bulk_args, .. } => {
+                assert_eq!(url.as_ref().map(|u| u.ends_with("/_sql")), Some(true));
+                assert_eq!(bulk_args, &Some(json!([[1], [2]])));
+            }
+            ref other => panic!("Unexpected call {:?}", other),
+        }
+    }
+
+    #[test]
+    fn compressed_sql() {
+        let server = FakeServer::start().unwrap();
+        let stmt = format!("select '{}'", "x".repeat(10_000));
+        server
+            .backend()
+            .on(stmt.clone(), MockResponse::rows(vec!["x"], vec![]));
+        let c = Cluster::from_config(&ClusterConfig {
+                                          urls: vec![server.url().to_string()],
+                                          compression: CompressionConfig {
+                                              request_threshold: Some(1024),
+                                              accept_compressed: true,
+                                          },
+                                          ..Default::default()
+                                      })
+                .unwrap();
+        assert!(c.query(stmt, None::<Box<NoParams>>).is_ok());
+    }
+
+    #[test]
+    fn blobs() {
+        let server = FakeServer::start().unwrap();
+        let c: Cluster = Cluster::from_string(server.url()).unwrap();
+
+        // larger than a single chunk of the upload
+        let content: Vec<u8> = (0..1_000_000).map(|i| (i % 251) as u8).collect();
+        let blob_ref = c.put("b", &mut Cursor::new(&content)).unwrap();
+        assert_eq!(server.backend().blob("b", &blob_ref.sha1), Some(content.clone()));
+        assert_eq!(blob_error_code(c.put("b", &mut Cursor::new(&content))), "409");
+
+        let mut fetched = vec![];
+        c.get(&blob_ref)
+            .unwrap()
+            .read_to_end(&mut fetched)
+            .unwrap();
+        assert_eq!(fetched, content);
+
+        c.delete(blob_ref.clone()).unwrap();
+        assert_eq!(blob_error_code(c.get(&blob_ref)), "404");
+        assert_eq!(blob_error_code(c.delete(blob_ref)), "404");
+    }
+
+    #[test]
+    fn digest_mismatch() {
+        let server = FakeServer::start().unwrap();
+        let c: Cluster = Cluster::from_string(server.url()).unwrap();
+        let result = c.backend
+            .upload_blob(Some(format!("{}_blobs", server.url())),
+                         "b",
+                         &[0; 20],
+                         &mut Cursor::new(b"content"));
+        assert_eq!(result, Ok(BackendResult::Error));
+        assert!(server.backend().calls().is_empty());
+    }
+
+    #[test]
+    fn failures() {
+        let server = FakeServer::start().unwrap();
+        let c: Cluster = Cluster::from_string(server.url()).unwrap();
+        let blob_ref = BlobRef {
+            sha1: vec![0; 20],
+            table: "b".to_string(),
+        };
+
+        server
+            .backend()
+            .set_blob_status(Some(BackendResult::NotAuthorized));
+        assert_eq!(blob_error_code(c.get(&blob_ref)), "403");
+
+        server.backend().set_transport_error(Some("node is restarting"));
+        let e = c.query("select 1", None::<Box<NoParams>>).unwrap_err();
+        assert_eq!(e.code, "5000");
+        assert_eq!(e.message, "node is restarting");
+        assert_eq!(blob_error_code(c.delete(blob_ref)), "500");
+    }
+}
@@ -0,0 +1,251 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate rustls;
+extern crate webpki;
+extern crate webpki_roots;
+
+use self::rustls::{ClientConfig, ClientSessionMemoryCache, Certificate, PrivateKey, RootCertStore,
+                   ServerCertVerifier, ServerCertVerified, TLSError};
+use self::rustls::internal::pemfile;
+use self::rustls::sign::any_supported_type;
+use std::fs::File;
+use std::io::{BufReader, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+use config::TlsConfig;
+use error::CrateDBConfigurationError;
+
+///
+/// Creates a rustls client configuration that trusts the webpki roots plus
+/// whatever the provided `TlsConfig` adds.
+///
+pub fn client_config(tls: &TlsConfig) -> Result<ClientConfig, CrateDBConfigurationError> {
+    let mut config = default_client_config();
+
+    for path in &tls.root_certificates {
+        for cert in load_certificates(path)? {
+            config
+                .root_store
+                .add(&cert)
+                .map_err(|e| {
+                             config_error(format!("Invalid root certificate in '{}': {:?}",
+                                                  path.display(),
+                                                  e))
+                         })?;
+        }
+    }
+
+    match (tls.client_certificate.as_ref(), tls.client_key.as_ref()) {
+        (Some(cert), Some(key)) => {
+            let chain = load_certificates(cert)?;
+            let key = load_private_key(key)?;
+            config.set_single_client_cert(chain, key);
+        }
+        (None, None) => {}
+        _ => {
+            return Err(config_error("A client certificate requires both a certificate and a \
+                                     key file"))
+        }
+    }
+
+    if tls.insecure_skip_verify {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(NoVerification));
+    }
+    Ok(config)
+}
+
+///
+/// The rustls client configuration `TlsClient::new()` would use.
+///
+pub fn default_client_config() -> ClientConfig {
+    let mut config = ClientConfig::new();
+    config.set_persistence(ClientSessionMemoryCache::new(64));
+    config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    config
+}
+
+fn config_error<S: Into<String>>(description: S) -> CrateDBConfigurationError {
+    CrateDBConfigurationError { description: description.into() }
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, CrateDBConfigurationError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| config_error(format!("Cannot open '{}': {}", path.display(), e)))
+}
+
+fn load_certificates(path: &Path) -> Result<Vec<Certificate>, CrateDBConfigurationError> {
+    match pemfile::certs(&mut open(path)?) {
+        Ok(ref certs) if certs.is_empty() => {
+            Err(config_error(format!("No certificates found in '{}'", path.display())))
+        }
+        Ok(certs) => Ok(certs),
+        Err(_) => Err(config_error(format!("Invalid PEM data in '{}'", path.display()))),
+    }
+}
+
+// Reads the first PKCS#8 or RSA (PKCS#1) private key from the file.
+fn load_private_key(path: &Path) -> Result<PrivateKey, CrateDBConfigurationError> {
+    let mut reader = open(path)?;
+    let mut keys = pemfile::pkcs8_private_keys(&mut reader).unwrap_or_default();
+    if keys.is_empty() {
+        reader
+            .seek(SeekFrom::Start(0))
+            .map_err(|e| config_error(format!("Cannot read '{}': {}", path.display(), e)))?;
+        keys = pemfile::rsa_private_keys(&mut reader).unwrap_or_default();
+    }
+    match keys.into_iter().next() {
+        Some(key) => {
+            any_supported_type(&key)
+                .map_err(|_| config_error(format!("Unsupported private key in '{}'", path.display())))?;
+            Ok(key)
+        }
+        None => Err(config_error(format!("No private key found in '{}'", path.display()))),
+    }
+}
+
+// Accepts any server certificate. Only ever used when explicitly configured.
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(&self,
+                          _roots: &RootCertStore,
+                          _presented_certs: &[Certificate],
+                          _dns_name: webpki::DNSNameRef,
+                          _ocsp_response: &[u8])
+                          -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate hyper;
+    extern crate hyper_rustls;
+
+    use super::*;
+    use self::hyper::server::{Server, Request, Response};
+    use self::hyper_rustls::TlsServer;
+    use self::rustls::{ServerConfig, NoClientAuth, AllowAnyAuthenticatedClient};
+    use backend::{Backend, BackendResult, DefaultHTTPBackend};
+    use std::mem;
+    use std::path::PathBuf;
+
+    const RESPONSE: &str = "{\"cols\":[],\"rows\":[],\"rowcount\":0,\"duration\":0.1}";
+
+    fn data(name: &str) -> PathBuf {
+        PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/tls")).join(name)
+    }
+
+    // Starts a TLS stand-in server for "localhost" and returns its port.
+    fn serve(client_auth: bool) -> u16 {
+        let certs = load_certificates(&data("server.pem")).unwrap();
+        let key = load_private_key(&data("server.key")).unwrap();
+        let mut cfg = if client_auth {
+            let mut roots = RootCertStore::empty();
+            for ca in load_certificates(&data("ca.pem")).unwrap() {
+                roots.add(&ca).unwrap();
+            }
+            ServerConfig::new(AllowAnyAuthenticatedClient::new(roots))
+        } else {
+            ServerConfig::new(NoClientAuth::new())
+        };
+        cfg.set_single_cert(certs, key).unwrap();
+
+        let server = Server::https("127.0.0.1:0", TlsServer { cfg: Arc::new(cfg) }).unwrap();
+        let listening = server
+            .handle(|_: Request, res: Response| { let _ = res.send(RESPONSE.as_bytes()); })
+            .unwrap();
+        let port = listening.socket.port();
+        // dropping would block until the server shuts down, which it never does
+        mem::forget(listening);
+        port
+    }
+
+    fn execute(port: u16, tls: &TlsConfig) -> Result<String, String> {
+        let mut backend = DefaultHTTPBackend::new();
+        backend.set_tls(tls).map_err(|e| e.description)?;
+        match backend.execute(Some(format!("https://localhost:{}/_sql", port)),
+                              "{\"stmt\": \"select 1\"}".to_string()) {
+            Ok((BackendResult::Ok, body)) => Ok(body),
+            Ok(_) => Err("Unexpected status".to_string()),
+            Err(e) => Err(e.description),
+        }
+    }
+
+    #[test]
+    fn untrusted_server() {
+        let port = serve(false);
+        assert!(execute(port, &TlsConfig::default()).is_err());
+    }
+
+    #[test]
+    fn custom_root_certificate() {
+        let port = serve(false);
+        let tls = TlsConfig { root_certificates: vec![data("ca.pem")], ..Default::default() };
+        assert_eq!(execute(port, &tls), Ok(RESPONSE.to_string()));
+    }
+
+    #[test]
+    fn insecure_skip_verify() {
+        let port = serve(false);
+        let tls = TlsConfig { insecure_skip_verify: true, ..Default::default() };
+        assert_eq!(execute(port, &tls), Ok(RESPONSE.to_string()));
+    }
+
+    #[test]
+    fn client_certificate() {
+        let port = serve(true);
+        let tls = TlsConfig { root_certificates: vec![data("ca.pem")], ..Default::default() };
+        assert!(execute(port, &tls).is_err());
+
+        let tls = TlsConfig {
+            root_certificates: vec![data("ca.pem")],
+            client_certificate: Some(data("client.pem")),
+            client_key: Some(data("client.key")),
+            ..Default::default()
+        };
+        assert_eq!(execute(port, &tls), Ok(RESPONSE.to_string()));
+    }
+
+    fn config_error_of(tls: &TlsConfig) -> String {
+        client_config(tls).err().unwrap().description
+    }
+
+    #[test]
+    fn invalid_configuration() {
+        let tls = TlsConfig { root_certificates: vec![data("missing.pem")], ..Default::default() };
+        assert!(config_error_of(&tls).starts_with("Cannot open"));
+
+        let tls = TlsConfig { root_certificates: vec![data("server.key")], ..Default::default() };
+        assert!(config_error_of(&tls).starts_with("No certificates found"));
+
+        let tls = TlsConfig { client_certificate: Some(data("client.pem")), ..Default::default() };
+        assert_eq!(config_error_of(&tls),
+                   "A client certificate requires both a certificate and a key file");
+
+        let tls = TlsConfig {
+            client_certificate: Some(data("client.pem")),
+            client_key: Some(data("client.pem")),
+            ..Default::default()
+        };
+        assert!(config_error_of(&tls).starts_with("No private key found"));
+    }
+}
@@ -0,0 +1,434 @@
+// Copyright 2016 Claus Matzinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs the HTTP transport against local plain and TLS stand-in servers.
+
+extern crate cratedb;
+extern crate hyper;
+extern crate hyper_rustls;
+extern crate rustls;
+extern crate flate2;
+#[macro_use]
+extern crate serde_json;
+
+use cratedb::{Cluster, NoParams};
+use cratedb::config::{ClusterConfig, TlsConfig, ProxyConfig, CompressionConfig};
+use cratedb::sql::QueryRunner;
+use cratedb::blob::BlobContainer;
+use cratedb::row::ByIndex;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use hyper::header::{AcceptEncoding, ContentEncoding, Encoding, Headers};
+use hyper::method::Method;
+use hyper::server::{Server, Request, Response, Listening};
+use hyper::status::StatusCode;
+use hyper_rustls::TlsServer;
+use rustls::{ServerConfig, NoClientAuth};
+use rustls::internal::pemfile;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Write};
+use std::mem;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+const BLOB: &[u8] = b"hello blob";
+
+fn data(name: &str) -> PathBuf {
+    PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/tls")).join(name)
+}
+
+// Answers every SQL request with the name of the scheme it was served on,
+// and counts the requests.
+fn handler(scheme: &'static str,
+           hits: Arc<AtomicUsize>)
+           -> impl Fn(Request, Response) + Send + Sync {
+    move |req: Request, mut res: Response| {
+        hits.fetch_add(1, Ordering::SeqCst);
+        let body = match req.method {
+            Method::Post => {
+                format!("{{\"cols\":[\"scheme\"],\"rows\":[[\"{}\"]],\"rowcount\":1,\
+                         \"duration\":0.1}}",
+                        scheme)
+                        .into_bytes()
+            }
+            Method::Put => {
+                *res.status_mut() = StatusCode::Created;
+                vec![]
+            }
+            Method::Get => BLOB.to_vec(),
+            _ => vec![],
+        };
+        let _ = res.send(&body);
+    }
+}
+
+// Dropping a Listening blocks until the server shuts down, which it never does.
+fn port_of(listening: Listening) -> u16 {
+    let port = listening.socket.port();
+    mem::forget(listening);
+    port
+}
+
+fn serve_http(hits: Arc<AtomicUsize>) -> u16 {
+    // the driver opens a new connection per request, idle ones would only tie up threads
+    let mut server = Server::http("127.0.0.1:0").unwrap();
+    server.keep_alive(None);
+    port_of(server.handle(handler("http", hits)).unwrap())
+}
+
+fn serve_https(hits: Arc<AtomicUsize>) -> u16 {
+    let certs = pemfile::certs(&mut BufReader::new(File::open(data("server.pem")).unwrap()))
+        .unwrap();
+    let mut keys =
+        pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(data("server.key")).unwrap()))
+            .unwrap();
+    let mut cfg = ServerConfig::new(NoClientAuth::new());
+    cfg.set_single_cert(certs, keys.remove(0)).unwrap();
+    let mut server = Server::https("127.0.0.1:0", TlsServer { cfg: Arc::new(cfg) }).unwrap();
+    server.keep_alive(None);
+    port_of(server.handle(handler("https", hits)).unwrap())
+}
+
+// "user:secret"
+const PROXY_CREDENTIALS: &str = "Proxy-Authorization: Basic dXNlcjpzZWNyZXQ=";
+
+fn read_head(stream: &mut TcpStream) -> io::Result<String> {
+    let mut head = vec![];
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte)? == 1 {
+        head.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+// Serves like `handler("http", ..)` and records every request's method and headers.
+fn serve_recording(seen: Arc<Mutex<Vec<(Method, Headers)>>>) -> u16 {
+    let mut server = Server::http("127.0.0.1:0").unwrap();
+    server.keep_alive(None);
+    let respond = handler("http", Arc::new(AtomicUsize::new(0)));
+    port_of(server
+                .handle(move |req: Request, res: Response| {
+                            seen.lock()
+                                .unwrap()
+                                .push((req.method.clone(), req.headers.clone()));
+                            respond(req, res)
+                        })
+                .unwrap())
+}
+
+// A forward proxy stand-in that requires PROXY_CREDENTIALS, answers plain
+// requests itself and tunnels CONNECT requests to `tunnel_port` on localhost.
+// The request heads it receives are recorded in `seen`.
+fn serve_proxy(tunnel_port: u16, seen: Arc<Mutex<Vec<String>>>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || for stream in listener.incoming() {
+                      let mut stream = stream.unwrap();
+                      let seen = seen.clone();
+                      thread::spawn(move || {
+            let head = read_head(&mut stream).unwrap();
+            seen.lock().unwrap().push(head.clone());
+            if !head.contains(PROXY_CREDENTIALS) {
+                let _ = stream.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\
+                                           Content-Length: 0\r\nConnection: close\r\n\r\n");
+            } else if head.starts_with("CONNECT ") {
+                let mut upstream = TcpStream::connect(("127.0.0.1", tunnel_port)).unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                    .unwrap();
+                let mut downstream = stream.try_clone().unwrap();
+                let mut upstream_reader = upstream.try_clone().unwrap();
+                thread::spawn(move || io::copy(&mut upstream_reader, &mut downstream));
+                let _ = io::copy(&mut stream, &mut upstream);
+            } else {
+                let length = head.lines()
+                    .filter_map(|l| l.strip_prefix("Content-Length: "))
+                    .filter_map(|l| l.trim().parse().ok())
+                    .next()
+                    .unwrap_or(0);
+                let mut body = vec![0u8; length];
+                stream.read_exact(&mut body).unwrap();
+                let response = "{\"cols\":[\"scheme\"],\"rows\":[[\"proxy\"]],\"rowcount\":1,\
+                                \"duration\":0.1}";
+                let _ = write!(stream,
+                               "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                               response.len(),
+                               response);
+            }
+        });
+                  });
+    port
+}
+
+// Answers SQL requests with the statement that was sent, decompressing the
+// request and compressing the response if the headers say so. Records whether
+// each request was compressed and its size on the wire.
+fn serve_gzip(requests: Arc<Mutex<Vec<(bool, usize)>>>) -> u16 {
+    let mut server = Server::http("127.0.0.1:0").unwrap();
+    server.keep_alive(None);
+    let listening = server
+        .handle(move |mut req: Request, mut res: Response| {
+            let mut raw = vec![];
+            req.read_to_end(&mut raw).unwrap();
+            let compressed = req.headers
+                .get::<ContentEncoding>()
+                .is_some_and(|e| e.contains(&Encoding::Gzip));
+            requests.lock().unwrap().push((compressed, raw.len()));
+
+            let mut payload = vec![];
+            if compressed {
+                GzDecoder::new(&raw[..]).read_to_end(&mut payload).unwrap();
+            } else {
+                payload = raw;
+            }
+            let sent: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+            let response = json!({
+                "cols": ["stmt"],
+                "rows": [[sent["stmt"]]],
+                "rowcount": 1,
+                "duration": 0.1
+            });
+            let body = serde_json::to_vec(&response).unwrap();
+
+            let accepts_gzip = req.headers
+                .get::<AcceptEncoding>()
+                .is_some_and(|a| a.iter().any(|q| q.item == Encoding::Gzip));
+            if accepts_gzip {
+                res.headers_mut().set(ContentEncoding(vec![Encoding::Gzip]));
+                let mut encoder = GzEncoder::new(vec![], Compression::default());
+                encoder.write_all(&body).unwrap();
+                let _ = res.send(&encoder.finish().unwrap());
+            } else {
+                let _ = res.send(&body);
+            }
+        })
+        .unwrap();
+    port_of(listening)
+}
+
+fn cluster_with_proxy(urls: Vec<String>, proxy: Option<ProxyConfig>) -> Cluster {
+    Cluster::from_config(&ClusterConfig {
+                              urls,
+                              tls: TlsConfig {
+                                  root_certificates: vec![data("ca.pem")],
+                                  ..Default::default()
+                              },
+                              proxy,
+                              ..Default::default()
+                          })
+            .unwrap()
+}
+
+fn cluster(urls: Vec<String>) -> Cluster {
+    cluster_with_proxy(urls, None)
+}
+
+fn proxy(port: u16, password: &str) -> Option<ProxyConfig> {
+    let mut proxy = ProxyConfig::new("127.0.0.1", port);
+    proxy.user = Some("user".to_string());
+    proxy.password = Some(password.to_string());
+    Some(proxy)
+}
+
+fn served_scheme(c: &Cluster) -> String {
+    let (_, mut rows) = c.query("select 1", None::<Box<NoParams>>).unwrap();
+    rows.next().unwrap().as_string(0).unwrap()
+}
+
+fn blob_roundtrip(c: &Cluster) {
+    let blob_ref = c.put("b", &mut Cursor::new(BLOB)).unwrap();
+    let mut content = vec![];
+    c.get(&blob_ref).unwrap().read_to_end(&mut content).unwrap();
+    assert_eq!(content, BLOB);
+    c.delete(blob_ref).unwrap();
+}
+
+#[test]
+fn plain_http() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let c = cluster(vec![format!("http://127.0.0.1:{}", serve_http(hits.clone()))]);
+    assert_eq!(served_scheme(&c), "http");
+    blob_roundtrip(&c);
+    assert_eq!(hits.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn https() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let c = cluster(vec![format!("https://localhost:{}", serve_https(hits.clone()))]);
+    assert_eq!(served_scheme(&c), "https");
+    blob_roundtrip(&c);
+    assert_eq!(hits.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn https_node_over_plain_connection() {
+    let port = serve_http(Arc::new(AtomicUsize::new(0)));
+    let c = cluster(vec![format!("https://localhost:{}", port)]);
+    assert!(c.query("select 1", None::<Box<NoParams>>).is_err());
+}
+
+#[test]
+fn mixed_schemes() {
+    let http_hits = Arc::new(AtomicUsize::new(0));
+    let https_hits = Arc::new(AtomicUsize::new(0));
+    let c = cluster(vec![format!("http://127.0.0.1:{}", serve_http(http_hits.clone())),
+                         format!("https://localhost:{}", serve_https(https_hits.clone()))]);
+    for _ in 0..10 {
+        served_scheme(&c);
+        blob_roundtrip(&c);
+    }
+    assert!(http_hits.load(Ordering::SeqCst) > 0);
+    assert!(https_hits.load(Ordering::SeqCst) > 0);
+    assert_eq!(http_hits.load(Ordering::SeqCst) + https_hits.load(Ordering::SeqCst),
+               40);
+}
+
+#[test]
+fn http_through_proxy() {
+    let seen = Arc::new(Mutex::new(vec![]));
+    let proxy_port = serve_proxy(0, seen.clone());
+    let c = cluster_with_proxy(vec!["http://db.invalid:4200".to_string()],
+                               proxy(proxy_port, "secret"));
+    assert_eq!(served_scheme(&c), "proxy");
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 1);
+    assert!(seen[0].starts_with("POST http://db.invalid:4200/_sql HTTP/1.1\r\n"));
+    assert!(seen[0].contains(PROXY_CREDENTIALS));
+}
+
+#[test]
+fn https_through_proxy_tunnel() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let https_port = serve_https(hits.clone());
+    let seen = Arc::new(Mutex::new(vec![]));
+    let proxy_port = serve_proxy(https_port, seen.clone());
+    let c = cluster_with_proxy(vec![format!("https://localhost:{}", https_port)],
+                               proxy(proxy_port, "secret"));
+    assert_eq!(served_scheme(&c), "https");
+    blob_roundtrip(&c);
+    assert_eq!(hits.load(Ordering::SeqCst), 4);
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 4);
+    for head in seen.iter() {
+        assert!(head.starts_with(&format!("CONNECT localhost:{} HTTP/1.1\r\n", https_port)));
+        assert!(head.contains(PROXY_CREDENTIALS));
+    }
+}
+
+#[test]
+fn proxy_authentication_failure() {
+    let https_port = serve_https(Arc::new(AtomicUsize::new(0)));
+    let proxy_port = serve_proxy(https_port, Arc::new(Mutex::new(vec![])));
+
+    let c = cluster_with_proxy(vec!["http://db.invalid:4200".to_string()],
+                               proxy(proxy_port, "wrong"));
+    assert!(c.query("select 1", None::<Box<NoParams>>).is_err());
+
+    let c = cluster_with_proxy(vec![format!("https://localhost:{}", https_port)],
+                               proxy(proxy_port, "wrong"));
+    let e = c.query("select 1", None::<Box<NoParams>>).unwrap_err();
+    assert!(e.message.contains("status 407"));
+}
+
+#[test]
+fn no_proxy() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let http_port = serve_http(hits.clone());
+    let seen = Arc::new(Mutex::new(vec![]));
+    let mut no_proxy = proxy(serve_proxy(0, seen.clone()), "secret").unwrap();
+    no_proxy.no_proxy = vec!["127.0.0.1".to_string()];
+
+    let c = cluster_with_proxy(vec![format!("http://127.0.0.1:{}", http_port)],
+                               Some(no_proxy));
+    assert_eq!(served_scheme(&c), "http");
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    assert!(seen.lock().unwrap().is_empty());
+}
+
+#[test]
+fn gzip_round_trip() {
+    let requests = Arc::new(Mutex::new(vec![]));
+    let url = format!("http://127.0.0.1:{}", serve_gzip(requests.clone()));
+    let c = Cluster::from_config(&ClusterConfig {
+                                      urls: vec![url],
+                                      compression: CompressionConfig {
+                                          request_threshold: Some(1024),
+                                          accept_compressed: true,
+                                      },
+                                      ..Default::default()
+                                  })
+            .unwrap();
+
+    let small = "select 1";
+    let large = format!("select '{}'", "x".repeat(100_000));
+    for stmt in &[small.to_string(), large.clone()] {
+        let (_, mut rows) = c.query(stmt.as_str(), None::<Box<NoParams>>).unwrap();
+        assert_eq!(&rows.next().unwrap().as_string(0).unwrap(), stmt);
+    }
+
+    let requests = requests.lock().unwrap();
+    assert!(!requests[0].0);
+    assert!(requests[1].0);
+    assert!(requests[1].1 < large.len() / 10);
+}
+
+#[test]
+fn uncompressed_by_default() {
+    let requests = Arc::new(Mutex::new(vec![]));
+    let c = cluster(vec![format!("http://127.0.0.1:{}", serve_gzip(requests.clone()))]);
+    let large = format!("select '{}'", "x".repeat(100_000));
+    let (_, mut rows) = c.query(large.as_str(), None::<Box<NoParams>>).unwrap();
+    assert_eq!(rows.next().unwrap().as_string(0).unwrap(), large);
+    assert!(!requests.lock().unwrap()[0].0);
+}
+
+#[test]
+fn custom_headers() {
+    let seen = Arc::new(Mutex::new(vec![]));
+    let mut config = ClusterConfig {
+        urls: vec![format!("http://127.0.0.1:{}", serve_recording(seen.clone()))],
+        user_agent: Some("metrics-service/2.1".to_string()),
+        ..Default::default()
+    };
+    config.headers.insert("X-Team".to_string(), "ops".to_string());
+    let mut c = Cluster::from_config(&config).unwrap();
+    let counter = Arc::new(AtomicUsize::new(0));
+    c.backend
+        .set_header_provider(move |_| {
+                                 let id = counter.fetch_add(1, Ordering::SeqCst);
+                                 vec![("X-Request-Id".to_string(), id.to_string())]
+                             });
+
+    served_scheme(&c);
+    blob_roundtrip(&c);
+
+    let seen = seen.lock().unwrap();
+    let methods: Vec<Method> = seen.iter().map(|s| s.0.clone()).collect();
+    assert_eq!(methods,
+               vec![Method::Post, Method::Put, Method::Get, Method::Delete]);
+    for (id, (_, headers)) in seen.iter().enumerate() {
+        assert_eq!(headers.get_raw("User-Agent").unwrap()[0],
+                   b"metrics-service/2.1".to_vec());
+        assert_eq!(headers.get_raw("X-Team").unwrap()[0], b"ops".to_vec());
+        assert_eq!(headers.get_raw("X-Request-Id").unwrap()[0],
+                   id.to_string().into_bytes());
+    }
+}